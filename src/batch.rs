@@ -0,0 +1,335 @@
+use crate::cli::{BatchArgs, RatingArgs, RatingKind};
+use crate::error::{Context, Error};
+use crate::ingest::{self, ImportOutcome, RatingPeriod};
+use crate::json::{EventInfo, EventSelection};
+use crate::reqwest_wrapper::{ClientOptions, ReqwestClient};
+use crate::rusqlite_wrapper::RusqliteConnection;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// A tournament listed in a batch file, and which of its events to ingest.
+/// Every event is ingested unless filtered by game or id.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    /// The slug or URL of the tournament. The URL of an event selects that
+    /// event.
+    pub slug: String,
+    /// Only ingest the events for these games, by name or smash.gg
+    /// videogame id.
+    #[serde(default)]
+    pub games: Vec<String>,
+    /// Only ingest the events with these ids.
+    #[serde(default)]
+    pub event_ids: Vec<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct BatchFile {
+    #[serde(default)]
+    tournament: Vec<BatchEntry>,
+}
+
+/// What happened to everything in a batch, for the summary at the end.
+#[derive(Default)]
+pub struct Summary {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Reads the tournaments listed in a batch file. Files ending in .toml list
+/// them as [[tournament]] tables. Any other file has one tournament slug per
+/// line, optionally followed by the games to ingest separated by commas.
+/// Blank lines and lines starting with # are ignored.
+pub fn read_batch_file(path: &str) -> Result<Vec<BatchEntry>, Error> {
+    let contents = fs::read_to_string(path)
+        .context(|| format!("reading the batch file {}", path))?;
+
+    let is_toml = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        let file: BatchFile = toml::from_str(&contents).map_err(|err| {
+            Error::InvalidInput(format!("Invalid batch file {}: {}", path, err))
+        })?;
+        return Ok(file.tournament);
+    }
+
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (slug, games) = line
+                .split_once(char::is_whitespace)
+                .unwrap_or((line, ""));
+            BatchEntry {
+                slug: slug.to_owned(),
+                games: games
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|game| !game.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+                event_ids: Vec::new(),
+            }
+        })
+        .collect())
+}
+
+/// Ingests every tournament listed in a batch file. The events of all the
+/// tournaments are looked up first and then ingested in the order they
+/// started, so ratings evolve in the order the sets were played. A failure
+/// doesn't stop the rest of the batch. A summary of what was imported,
+/// skipped or failed is printed at the end.
+pub fn batch(
+    token_env: &str,
+    db_path: &str,
+    ratings: RatingArgs,
+    client_options: ClientOptions,
+    args: BatchArgs,
+) -> Result<(), Error> {
+    let entries = read_batch_file(&args.file)?;
+    let mut reqwest_client = ingest::connect(token_env, client_options)?;
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+    let rating_kinds =
+        ingest::resolve_rating_kinds(&rusqlite_connection, ratings)?;
+
+    let mut summary = Summary::default();
+    let mut events: Vec<(String, EventInfo)> = Vec::new();
+    println!("Looking up the events of {} tournaments...", entries.len());
+    for entry in &entries {
+        match select_events(&mut reqwest_client, entry) {
+            Ok((slug, found)) => events
+                .extend(found.into_iter().map(|event| (slug.clone(), event))),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                summary.failed.push(entry.slug.clone());
+            }
+        }
+    }
+
+    ingest_events(
+        &mut reqwest_client,
+        &rusqlite_connection,
+        &rating_kinds,
+        events,
+        args.reimport,
+        summary,
+    )
+}
+
+/// Ingests the given events of any number of tournaments in the order they
+/// started, carrying on past any that fail. A summary is printed at the end,
+/// along with anything that failed before the events were found.
+pub fn ingest_events(
+    reqwest_client: &mut ReqwestClient,
+    rusqlite_connection: &RusqliteConnection,
+    rating_kinds: &[RatingKind],
+    mut events: Vec<(String, EventInfo)>,
+    reimport: bool,
+    mut summary: Summary,
+) -> Result<(), Error> {
+    // Tournaments are ingested in the order they started, each one's events
+    // together and in the order they started, as a tournament is one rating
+    // period. Events without a start time go last, in the order they were
+    // listed.
+    let start = |event: &EventInfo| event.start_at.unwrap_or(i64::MAX);
+    let mut tournaments: HashMap<String, (i64, usize)> = HashMap::new();
+    for (listed, (slug, event)) in events.iter().enumerate() {
+        let (tournament_start, _) =
+            tournaments.entry(slug.clone()).or_insert((i64::MAX, listed));
+        *tournament_start = (*tournament_start).min(start(event));
+    }
+    events.sort_by_key(|(slug, event)| (tournaments[slug], start(event)));
+
+    let mut period = RatingPeriod::new(rating_kinds);
+    for (count, (slug, event)) in events.iter().enumerate() {
+        if count > 0 && events[count - 1].0 != *slug {
+            ingest::end_period(rusqlite_connection, &mut period)?;
+        }
+
+        let name = format!("{} - {} from {}", event.game_name, event.name, slug);
+        let outcome = ingest::ingest_event(
+            reqwest_client,
+            rusqlite_connection,
+            &mut period,
+            slug,
+            event,
+            reimport,
+        );
+        match outcome {
+            Ok(ImportOutcome::Imported) => summary.imported.push(name),
+            Ok(ImportOutcome::Skipped) => summary.skipped.push(name),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                summary.failed.push(name);
+            }
+        }
+    }
+
+    ingest::end_period(rusqlite_connection, &mut period)?;
+
+    print_summary(&summary);
+    match summary.failed.len() {
+        0 => Ok(()),
+        failed => Err(Error::Batch(failed)),
+    }
+}
+
+// Looks up the events of a tournament in a batch and picks out the ones
+// asked for. The slug may also be the URL of the tournament or one of its
+// events. Returns the tournament's slug along with its events.
+fn select_events(
+    reqwest_client: &mut ReqwestClient,
+    entry: &BatchEntry,
+) -> Result<(String, Vec<EventInfo>), Error> {
+    let (slug, event_slug) = ingest::parse_tournament(&entry.slug);
+    let selection = match event_slug {
+        Some(event_slug) if entry.games.is_empty() => {
+            EventSelection::Slug(event_slug)
+        }
+        _ => EventSelection::Games(entry.games.clone()),
+    };
+    let mut events = ingest::fetch_events(reqwest_client, &slug, &selection)?;
+
+    if !entry.event_ids.is_empty() {
+        events.retain(|event| entry.event_ids.contains(&event.id));
+        if events.is_empty() {
+            return Err(Error::InvalidInput(format!(
+                "None of the events {:?} are in tournament {}",
+                entry.event_ids, slug
+            )));
+        }
+    }
+
+    Ok((slug, events))
+}
+
+fn print_summary(summary: &Summary) {
+    println!(
+        "Batch finished: {} imported, {} skipped, {} failed",
+        summary.imported.len(),
+        summary.skipped.len(),
+        summary.failed.len()
+    );
+
+    for (label, names) in [
+        ("Imported", &summary.imported),
+        ("Skipped", &summary.skipped),
+        ("Failed", &summary.failed),
+    ] {
+        for name in names {
+            println!("  {}: {}", label, name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // The slug, games and event ids of a tournament in a batch file.
+    type Entry = (String, Vec<String>, Vec<i32>);
+
+    // Writes a batch file to a scratch directory and reads it back.
+    fn read(name: &str, contents: &str) -> Result<Vec<Entry>, Error> {
+        let dir = env::temp_dir()
+            .join(format!("smashgg_elo-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        let entries = read_batch_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        Ok(entries?
+            .into_iter()
+            .map(|entry| (entry.slug, entry.games, entry.event_ids))
+            .collect())
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_a_slug_and_games_per_line() {
+        let entries = read(
+            "circuit.txt",
+            "# Weeklies\n\
+            weekly-1 Super Smash Bros. Melee, 1386 ,\n\
+            \n  https://start.gg/tournament/weekly-2/details  \n\
+            weekly-3\tUltimate\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    "weekly-1".to_owned(),
+                    strings(&["Super Smash Bros. Melee", "1386"]),
+                    vec![]
+                ),
+                (
+                    "https://start.gg/tournament/weekly-2/details".to_owned(),
+                    vec![],
+                    vec![]
+                ),
+                ("weekly-3".to_owned(), strings(&["Ultimate"]), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_tournament_tables_from_toml_files() {
+        let entries = read(
+            "circuit.TOML",
+            "[[tournament]]\n\
+            slug = \"weekly-1\"\n\
+            games = [\"Super Smash Bros. Melee\"]\n\
+            \n\
+            [[tournament]]\n\
+            slug = \"weekly-2\"\n\
+            event_ids = [123456]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    "weekly-1".to_owned(),
+                    strings(&["Super Smash Bros. Melee"]),
+                    vec![]
+                ),
+                ("weekly-2".to_owned(), vec![], vec![123456]),
+            ]
+        );
+        assert!(read("empty.toml", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_toml_files() {
+        for contents in [
+            "[[tournament]]\nslug = \"weekly-1\"\ngame = [\"Melee\"]\n",
+            "[[tournament]]\ngames = [\"Melee\"]\n",
+            "slug = ",
+        ] {
+            let err = read("circuit.toml", contents).unwrap_err();
+            assert!(matches!(err, Error::InvalidInput(_)), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn fails_to_read_a_missing_file() {
+        let err = read_batch_file("/nonexistent/circuit.txt").unwrap_err();
+        assert_eq!(err.exit_code(), crate::cli::EXIT_FAILURE);
+    }
+}
@@ -0,0 +1,344 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::fixtures::Fixtures;
+use crate::json::EventSelection;
+use crate::reqwest_wrapper::ClientOptions;
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const DEFAULT_TOKEN_ENV: &str = "SMASHGG_TOKEN";
+pub const DEFAULT_DB_PATH: &str = "./database/smashgg.db3";
+
+// Exit codes returned by the binary. Clap already exits with 2 when it fails
+// to parse the command line, so missing or invalid input shares that code.
+pub const EXIT_SUCCESS: u8 = 0;
+pub const EXIT_FAILURE: u8 = 1;
+pub const EXIT_USAGE: u8 = 2;
+pub const EXIT_API: u8 = 3;
+pub const EXIT_DATABASE: u8 = 4;
+
+/// Queries smash.gg for tournament data and ranks players using Elo.
+///
+/// Running without a subcommand behaves like `ingest` and prompts for
+/// anything that was not given on the command line.
+#[derive(Parser, Debug)]
+#[command(name = "smashgg_elo", version)]
+pub struct Cli {
+    /// Name of the environment variable holding the smash.gg auth token.
+    #[arg(long, global = true, default_value = DEFAULT_TOKEN_ENV)]
+    pub token_env: String,
+
+    /// Path to the sqlite database to record data in. Falls back to the
+    /// SMASHGG_DB environment variable, then the config file, then
+    /// ./database/smashgg.db3.
+    #[arg(long, global = true)]
+    pub db: Option<String>,
+
+    /// Name of a database listed under [databases] in the config file, to
+    /// keep separate databases for regions or circuits.
+    #[arg(long, global = true, conflicts_with = "db")]
+    pub database: Option<String>,
+
+    /// Path to the config file. Falls back to the SMASHGG_CONFIG environment
+    /// variable, then ./smashgg_elo.toml if it exists.
+    #[arg(long, global = true)]
+    pub config: Option<String>,
+
+    #[command(flatten)]
+    pub ratings: RatingArgs,
+
+    #[command(flatten)]
+    pub requests: RequestArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// How sets are rated. Both choices are saved in the database.
+#[derive(Args, Debug, Clone)]
+pub struct RatingArgs {
+    /// Rating systems used by the database, separated by commas. Players are
+    /// ranked by the first. Only needed when creating a new database or
+    /// recomputing, as the choice is saved in it. Defaults to elo.
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    pub rating_system: Vec<RatingKind>,
+
+    /// Also rate each member of a team in the singles tables when sets are
+    /// between teams, such as in doubles. Teams are otherwise only rated in
+    /// tables of their own. The choice is saved in the database and applies
+    /// to events recorded from then on, or to every event by recomputing.
+    #[arg(long, global = true, num_args = 0..=1, require_equals = true,
+        default_missing_value = "true")]
+    pub doubles_in_singles: Option<bool>,
+}
+
+/// How requests to smash.gg are paced and retried.
+#[derive(Args, Debug)]
+pub struct RequestArgs {
+    /// URL of the GraphQL api to send requests to, such as a local stand-in
+    /// for testing. Falls back to the SMASHGG_ENDPOINT environment variable,
+    /// then the config file, then https://api.start.gg/gql/alpha.
+    #[arg(long, global = true)]
+    pub endpoint: Option<String>,
+
+    /// Most requests sent to smash.gg within each budget window.
+    #[arg(long, global = true, default_value_t = 80,
+        value_parser = clap::value_parser!(u32).range(1..))]
+    pub request_budget: u32,
+
+    /// Length of the request budget window in seconds.
+    #[arg(long, global = true, default_value_t = 60,
+        value_parser = clap::value_parser!(u64).range(1..))]
+    pub budget_window: u64,
+
+    /// Seconds to wait for a response before the request is retried.
+    #[arg(long, global = true, default_value_t = 30,
+        value_parser = clap::value_parser!(u64).range(1..))]
+    pub request_timeout: u64,
+
+    /// Times a request is retried after a network failure, the rate limit
+    /// or a server error before giving up.
+    #[arg(long, global = true, default_value_t = 5)]
+    pub max_retries: u32,
+
+    /// Most pages of entrants or sets of an event requested at once. The
+    /// workers share the request budget.
+    #[arg(long, global = true, default_value_t = 4,
+        value_parser = clap::value_parser!(u64).range(1..64))]
+    pub workers: u64,
+
+    /// Record every request sent to smash.gg and its response in this
+    /// directory, to be replayed later.
+    #[arg(long, global = true, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Answer requests with the responses recorded in this directory
+    /// instead of sending them to smash.gg.
+    #[arg(long, global = true)]
+    pub replay: Option<PathBuf>,
+
+    /// Don't cache responses from smash.gg in the database.
+    #[arg(long, global = true)]
+    pub no_cache: bool,
+
+    /// Seconds that responses about events that haven't completed are
+    /// cached for. Responses about completed events are cached forever.
+    #[arg(long, global = true, default_value_t = 600)]
+    pub cache_ttl: u64,
+}
+
+impl RequestArgs {
+    /// The options requests are sent with. Fails if the endpoint isn't an
+    /// http or https URL.
+    pub fn client_options(
+        &self,
+        config: &Config,
+        db_path: &str,
+    ) -> Result<ClientOptions, Error> {
+        let endpoint = config.endpoint(self.endpoint.as_deref());
+        match reqwest::Url::parse(&endpoint) {
+            Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+            _ => {
+                return Err(Error::InvalidInput(format!(
+                    "Invalid endpoint {:?}: expected an http or https URL",
+                    endpoint
+                )))
+            }
+        }
+
+        Ok(ClientOptions {
+            endpoint,
+            request_budget: self.request_budget,
+            budget_window: Duration::from_secs(self.budget_window),
+            timeout: Duration::from_secs(self.request_timeout),
+            max_retries: self.max_retries,
+            workers: self.workers as usize,
+            fixtures: match (&self.record, &self.replay) {
+                (Some(dir), _) => Some(Fixtures::Record(dir.clone())),
+                (_, Some(dir)) => Some(Fixtures::Replay(dir.clone())),
+                _ => None,
+            },
+            cache_path: (!self.no_cache).then(|| db_path.to_owned()),
+            cache_ttl: Duration::from_secs(self.cache_ttl),
+        })
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Parse a single event of a tournament and record it in the database.
+    Ingest(IngestArgs),
+    /// Parse every tournament listed in a file, in the order they started.
+    Batch(BatchArgs),
+    /// Search smash.gg for tournaments by game, region and date, and list or
+    /// parse their events.
+    Discover(DiscoverArgs),
+    /// Recalculate every rating and statistic by replaying all recorded sets.
+    /// Rating systems given with --rating-system replace the saved choice.
+    Recompute,
+    /// Merge one player into another, such as a duplicate account or a guest
+    /// who has since made an account, and recalculate every rating.
+    MergePlayers(MergeArgs),
+    /// Rank the players of a region defined in the config file by the
+    /// ratings they earned everywhere, and save the ranks in the database.
+    Leaderboard(LeaderboardArgs),
+}
+
+#[derive(Args, Debug, Default)]
+pub struct IngestArgs {
+    /// The tournament slug, i.e. start.gg/tournament/[tournament slug]/...,
+    /// or the URL of the tournament. A URL of an event selects that event.
+    #[arg(long)]
+    pub slug: Option<String>,
+
+    /// The smash.gg id of the event to parse within the tournament.
+    #[arg(long, conflicts_with_all = ["all_events", "game"])]
+    pub event_id: Option<i32>,
+
+    /// Parse every event of the tournament.
+    #[arg(long)]
+    pub all_events: bool,
+
+    /// Parse every event of the tournament for the given games, by name or
+    /// smash.gg videogame id, separated by commas.
+    #[arg(long, value_delimiter = ',')]
+    pub game: Vec<String>,
+
+    /// Import the event again if it has already been imported, replacing its
+    /// sets and replaying every event. Otherwise it is skipped.
+    #[arg(long)]
+    pub reimport: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// File listing the tournaments to parse. Files ending in .toml list
+    /// [[tournament]] tables with a slug and optional games and event_ids.
+    /// Otherwise each line has a tournament slug, optionally followed by
+    /// games separated by commas.
+    pub file: String,
+
+    /// Import events again if they have already been imported, replacing
+    /// their sets. Otherwise they are skipped.
+    #[arg(long)]
+    pub reimport: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(allow_negative_numbers = true)]
+pub struct MergeArgs {
+    /// Global id of the player to merge away. Guests have negative ids.
+    pub from: i32,
+
+    /// Global id of the player to merge them into.
+    pub into: i32,
+}
+
+#[derive(Args, Debug)]
+pub struct LeaderboardArgs {
+    /// Region to rank, as named under [regions] in the config file. Every
+    /// region is ranked if none is given.
+    pub region: Option<String>,
+
+    /// Rank by the ratings earned in this game rather than in every game,
+    /// given by its name on smash.gg or its videogame id.
+    #[arg(long)]
+    pub game: Option<String>,
+
+    /// Number of players shown for each region. Every player's rank is
+    /// still saved.
+    #[arg(long, default_value_t = 25)]
+    pub top: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct DiscoverArgs {
+    /// Only find events for these smash.gg videogame ids, separated by
+    /// commas.
+    #[arg(long, value_delimiter = ',')]
+    pub videogame_id: Vec<i32>,
+
+    /// Only find tournaments in this country, by its two letter code.
+    #[arg(long)]
+    pub country: Option<String>,
+
+    /// Only find tournaments in this state or province, by its code.
+    #[arg(long)]
+    pub state: Option<String>,
+
+    /// Only find tournaments near a point, given as latitude,longitude.
+    #[arg(long, allow_hyphen_values = true)]
+    pub near: Option<String>,
+
+    /// How far from --near tournaments can be, such as 50mi or 80km.
+    #[arg(long, default_value = "50mi", requires = "near")]
+    pub radius: String,
+
+    /// Only find tournaments starting on or after this date (YYYY-MM-DD).
+    #[arg(long)]
+    pub after: Option<NaiveDate>,
+
+    /// Only find tournaments starting on or before this date (YYYY-MM-DD).
+    #[arg(long)]
+    pub before: Option<NaiveDate>,
+
+    /// Only find online events.
+    #[arg(long, conflicts_with = "offline")]
+    pub online: bool,
+
+    /// Only find offline events.
+    #[arg(long)]
+    pub offline: bool,
+
+    /// Most tournaments to find.
+    #[arg(long, default_value_t = 50)]
+    pub limit: usize,
+
+    /// Parse every event found instead of only listing them.
+    #[arg(long)]
+    pub ingest: bool,
+
+    /// Import events again if they have already been imported, replacing
+    /// their sets. Otherwise they are skipped.
+    #[arg(long, requires = "ingest")]
+    pub reimport: bool,
+}
+
+impl IngestArgs {
+    /// Which events of the tournament were asked for. An event slug from
+    /// the URL of an event is used unless events were chosen by id or game.
+    pub fn event_selection(
+        &self,
+        event_slug: Option<String>,
+    ) -> EventSelection {
+        match self.event_id {
+            Some(event_id) => EventSelection::Id(event_id),
+            None if self.all_events || !self.game.is_empty() => {
+                EventSelection::Games(self.game.clone())
+            }
+            None => match event_slug {
+                Some(event_slug) => EventSelection::Slug(event_slug),
+                None => EventSelection::Prompt,
+            },
+        }
+    }
+}
+
+/// The rating systems a database can be set up with.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RatingKind {
+    Elo,
+    Glicko2,
+}
+
+impl RatingKind {
+    /// Name of the rating system as saved in the settings table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RatingKind::Elo => "elo",
+            RatingKind::Glicko2 => "glicko2",
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::cli;
+use crate::error::{Context, Error};
+use crate::reqwest_wrapper::DEFAULT_ENDPOINT;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_ENV: &str = "SMASHGG_CONFIG";
+pub const DB_ENV: &str = "SMASHGG_DB";
+pub const ENDPOINT_ENV: &str = "SMASHGG_ENDPOINT";
+const DEFAULT_CONFIG_PATH: &str = "./smashgg_elo.toml";
+
+// Versions before the database path could be configured wrote to this
+// misspelled file. It is still used if it exists and the default doesn't.
+const LEGACY_DB_PATH: &str = "./database/smashhgg.db3";
+
+/// Settings read from the config file. Everything in it can be overridden
+/// on the command line.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Path to the database used when no other is chosen.
+    pub db: Option<String>,
+    /// Named databases, so separate regions or circuits can be kept apart
+    /// and chosen with --database.
+    pub databases: HashMap<String, String>,
+    /// URL of the GraphQL api to send requests to.
+    pub endpoint: Option<String>,
+    /// Regions that players can be ranked within, such as for a power
+    /// ranking panel.
+    pub regions: HashMap<String, Region>,
+}
+
+/// A region defined by where players live, as given on their smash.gg
+/// profile, and by players listed by hand. A player is in the region if
+/// they are listed, or if they live in one of its countries, states and
+/// cities, leaving out any that aren't given. Listing nothing but players
+/// makes a region of just those players.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Region {
+    /// Countries in the region, as named on smash.gg.
+    pub countries: Vec<String>,
+    /// States or provinces in the region, as abbreviated on smash.gg.
+    pub states: Vec<String>,
+    pub cities: Vec<String>,
+    /// Global ids of players in the region wherever they live.
+    pub players: Vec<i32>,
+    /// Global ids of players left out of the region wherever they live.
+    pub exclude: Vec<i32>,
+}
+
+impl Region {
+    /// Returns true if a player with the given id and location belongs to
+    /// the region.
+    pub fn contains(
+        &self,
+        global_id: i32,
+        country: Option<&str>,
+        state: Option<&str>,
+        city: Option<&str>,
+    ) -> bool {
+        if self.exclude.contains(&global_id) {
+            return false;
+        }
+        if self.players.contains(&global_id) {
+            return true;
+        }
+        if self.countries.is_empty() && self.states.is_empty() && self.cities.is_empty() {
+            return false;
+        }
+
+        let matches = |places: &[String], place: Option<&str>| {
+            places.is_empty()
+                || place.is_some_and(|place| {
+                    places
+                        .iter()
+                        .any(|name| name.trim().eq_ignore_ascii_case(place.trim()))
+                })
+        };
+        matches(&self.countries, country)
+            && matches(&self.states, state)
+            && matches(&self.cities, city)
+    }
+}
+
+impl Config {
+    /// Reads the config file at the given path, or from the path in the
+    /// config environment variable. Without either, the default config
+    /// file is read if there is one.
+    pub fn load(path: Option<&str>) -> Result<Self, Error> {
+        let path = path
+            .map(str::to_owned)
+            .or_else(|| std::env::var(CONFIG_ENV).ok());
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                DEFAULT_CONFIG_PATH.to_owned()
+            }
+            None => return Ok(Config::default()),
+        };
+
+        let contents = fs::read_to_string(&path)
+            .context(|| format!("reading the config file {}", path))?;
+        toml::from_str(&contents).map_err(|err| {
+            Error::InvalidInput(format!("Invalid config file {}: {}", path, err))
+        })
+    }
+
+    /// Picks the database to use. A path given with --db comes first, then
+    /// a database named with --database, then the database environment
+    /// variable, and then the path in the config file.
+    pub fn database_path(
+        &self,
+        db: Option<&str>,
+        database: Option<&str>,
+    ) -> Result<String, Error> {
+        if let Some(db) = db {
+            return Ok(db.to_owned());
+        }
+        if let Some(name) = database {
+            return self.databases.get(name).cloned().ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "No database named {:?} in the config file",
+                    name
+                ))
+            });
+        }
+
+        let path = std::env::var(DB_ENV)
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .or_else(|| self.db.clone());
+        Ok(path.unwrap_or_else(|| {
+            match !Path::new(cli::DEFAULT_DB_PATH).exists()
+                && Path::new(LEGACY_DB_PATH).exists()
+            {
+                true => LEGACY_DB_PATH.to_owned(),
+                false => cli::DEFAULT_DB_PATH.to_owned(),
+            }
+        }))
+    }
+
+    /// Picks the api endpoint. One given with --endpoint comes first, then
+    /// the endpoint environment variable, then the config file.
+    pub fn endpoint(&self, endpoint: Option<&str>) -> String {
+        endpoint
+            .map(str::to_owned)
+            .or_else(|| {
+                std::env::var(ENDPOINT_ENV)
+                    .ok()
+                    .filter(|endpoint| !endpoint.trim().is_empty())
+            })
+            .or_else(|| self.endpoint.clone())
+            .unwrap_or_else(|| DEFAULT_ENDPOINT.to_owned())
+    }
+}
+
+/// Creates any missing directories leading up to the database file.
+pub fn create_parent_dirs(db_path: &str) -> Result<(), Error> {
+    match Path::new(db_path).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            fs::create_dir_all(parent).context(|| {
+                format!("creating the directory {}", parent.display())
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn places_players_by_location_and_by_hand() {
+        let norcal = Region {
+            countries: strings(&["United States"]),
+            states: strings(&["CA"]),
+            cities: strings(&["San Francisco", "San Jose"]),
+            players: vec![1],
+            exclude: vec![2],
+        };
+        let cases = [
+            (3, Some("United States"), Some("CA"), Some("San Jose"), true),
+            (3, Some("united states "), Some("ca"), Some("SAN JOSE"), true),
+            (3, Some("United States"), Some("CA"), Some("Los Angeles"), false),
+            (3, Some("United States"), Some("NV"), Some("San Jose"), false),
+            (3, Some("United States"), Some("CA"), None, false),
+            (3, None, None, None, false),
+            (1, Some("Japan"), None, Some("Tokyo"), true),
+            (1, None, None, None, true),
+            (2, Some("United States"), Some("CA"), Some("San Jose"), false),
+        ];
+
+        for (global_id, country, state, city, expected) in cases {
+            assert_eq!(
+                norcal.contains(global_id, country, state, city),
+                expected,
+                "{} {:?} {:?} {:?}",
+                global_id,
+                country,
+                state,
+                city
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_out_places_that_arent_given() {
+        let california = Region {
+            states: strings(&["CA"]),
+            ..Region::default()
+        };
+        assert!(california.contains(3, Some("United States"), Some("CA"), None));
+        assert!(california.contains(3, None, Some("CA"), Some("Oakland")));
+        assert!(!california.contains(3, Some("United States"), None, None));
+    }
+
+    #[test]
+    fn is_only_the_players_listed_without_places() {
+        let panel = Region {
+            players: vec![1, 2],
+            exclude: vec![2],
+            ..Region::default()
+        };
+        assert!(panel.contains(1, None, None, None));
+        assert!(!panel.contains(2, None, None, None));
+        assert!(!panel.contains(3, Some("United States"), Some("CA"), None));
+        assert!(!Region::default().contains(1, None, None, None));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::batch::{self, Summary};
+use crate::cli::{DiscoverArgs, RatingArgs};
+use crate::error::{Context, Error};
+use crate::ingest;
+use crate::json::DiscoveredTournament;
+use crate::reqwest_wrapper::{
+    ClientOptions, Content, ContentType, LocationFilter, ReqwestClient,
+};
+use crate::rusqlite_wrapper::RusqliteConnection;
+use chrono::{NaiveDate, NaiveTime, TimeZone, Utc};
+
+/// Searches smash.gg for tournaments matching the given filters and lists
+/// them along with their events. If asked to, every event found is then
+/// ingested in the order they started.
+pub fn discover(
+    token_env: &str,
+    db_path: &str,
+    ratings: RatingArgs,
+    client_options: ClientOptions,
+    args: DiscoverArgs,
+) -> Result<(), Error> {
+    let mut reqwest_client = ingest::connect(token_env, client_options)?;
+
+    let tournaments = search(&mut reqwest_client, &args)?;
+    println!("Found {} tournaments:", tournaments.len());
+    for tournament in &tournaments {
+        println!(
+            "{} {} ({}) - {}",
+            format_date(tournament.start_at),
+            tournament.slug,
+            tournament.name,
+            tournament.location
+        );
+        for event in &tournament.events {
+            println!("    {}: {} - {}", event.id, event.game_name, event.name);
+        }
+    }
+
+    if !args.ingest {
+        return Ok(());
+    }
+
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+    let rating_kinds =
+        ingest::resolve_rating_kinds(&rusqlite_connection, ratings)?;
+    // Events that haven't completed have sets still to be played, so only
+    // completed events are ingested and the rest are reported as skipped.
+    let (events, upcoming): (Vec<_>, Vec<_>) = tournaments
+        .into_iter()
+        .flat_map(|tournament| {
+            let slug = tournament.slug;
+            tournament
+                .events
+                .into_iter()
+                .map(move |event| (slug.clone(), event))
+        })
+        .partition(|(_, event)| event.is_completed());
+    let summary = Summary {
+        skipped: upcoming
+            .iter()
+            .map(|(slug, event)| {
+                format!(
+                    "{} - {} from {} (not completed)",
+                    event.game_name, event.name, slug
+                )
+            })
+            .collect(),
+        ..Summary::default()
+    };
+
+    batch::ingest_events(
+        &mut reqwest_client,
+        &rusqlite_connection,
+        &rating_kinds,
+        events,
+        args.reimport,
+        summary,
+    )
+}
+
+// Requests pages of tournaments matching the filters until every page has
+// been read or the limit is reached. Only events matching the online or
+// offline filter are kept, and tournaments left without events are dropped.
+fn search(
+    reqwest_client: &mut ReqwestClient,
+    args: &DiscoverArgs,
+) -> Result<Vec<DiscoveredTournament>, Error> {
+    let mut content = Content::new();
+    content.edit_content(ContentType::Discover);
+    let variables = &mut content.variables;
+    variables.videogame_ids =
+        Some(args.videogame_id.clone()).filter(|ids| !ids.is_empty());
+    variables.country_code = args.country.clone();
+    variables.addr_state = args.state.clone();
+    variables.location = args.near.as_ref().map(|near| LocationFilter {
+        distance_from: near.to_owned(),
+        distance: args.radius.to_owned(),
+    });
+    variables.after_date = args.after.map(start_of_day);
+    // The day given as the end of the window is included in it.
+    variables.before_date =
+        args.before.and_then(|date| date.succ_opt()).map(start_of_day);
+    variables.has_online_events = args.online.then_some(true);
+
+    let mut found = Vec::new();
+    let mut page = 1;
+    loop {
+        content.variables.page = Some(page);
+        reqwest_client.construct_json(&content);
+        let (tournaments, total_pages) = reqwest_client
+            .send_post()
+            .and_then(|json| json.get_tournaments())
+            .context(|| format!("searching page {} of tournaments", page))?;
+        println!("Searched page {} out of {}...", page, total_pages);
+
+        for mut tournament in tournaments {
+            tournament.events.retain(|event| match (args.online, args.offline) {
+                (true, _) => event.is_online == Some(true),
+                (_, true) => event.is_online != Some(true),
+                _ => true,
+            });
+            if !tournament.events.is_empty() {
+                found.push(tournament);
+            }
+        }
+
+        if page >= total_pages || found.len() >= args.limit {
+            break;
+        }
+        page += 1;
+    }
+
+    found.truncate(args.limit);
+    Ok(found)
+}
+
+fn start_of_day(date: NaiveDate) -> i64 {
+    date.and_time(NaiveTime::MIN).and_utc().timestamp()
+}
+
+fn format_date(timestamp: Option<i64>) -> String {
+    timestamp
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single())
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "????-??-??".to_string())
+}
@@ -1,15 +1,11 @@
+use crate::rating::{RatingChange, RatingSystem, SetResult};
 use crate::PlayersRow;
 
 const K_FACTOR_LIMIT: i32 = 20;
 const K_FACTOR_STANDARD: f64 = 24.0;
 const K_FACTOR_PROVISIONAL: f64 = 32.0;
 
-pub struct Elo {
-    pub player_one: PlayersRow,
-    pub score_one: i32,
-    pub player_two: PlayersRow,
-    pub score_two: i32,
-}
+pub struct Elo;
 
 impl Elo {
     /// Calculates the expected score used for the Elo algorithm. The formula
@@ -18,12 +14,12 @@ impl Elo {
     /// E_{p_1} = Q_{p_1} / (Q_{p_1} + Q_{p_2})
     ///
     /// Where Q_{p_1} = 10^(Elo_{p_1} / 400), Q_{p_2} = 10^(Elo_{p_2} / 400)
-    fn expected_scores(&self, num_games: i32) -> (f64, f64) {
+    fn expected_scores(elo_one: f64, elo_two: f64, num_games: i32) -> (f64, f64) {
         let (mut ex_score_one, mut ex_score_two) = (0.0, 0.0);
 
         for _games in 0..num_games {
-            let quotient_one = f64::powf(10.0, self.player_one.elo / 400.0);
-            let quotient_two = f64::powf(10.0, self.player_two.elo / 400.0);
+            let quotient_one = f64::powf(10.0, elo_one / 400.0);
+            let quotient_two = f64::powf(10.0, elo_two / 400.0);
             ex_score_one += quotient_one / (quotient_one + quotient_two);
             ex_score_two += quotient_two / (quotient_one + quotient_two);
         }
@@ -31,44 +27,57 @@ impl Elo {
         (ex_score_one, ex_score_two)
     }
 
+    /// Calculates the k-factor for a player. If the player has played under
+    /// 20 total games for any game, they are assigned provisional.
+    fn k_factor(player: &PlayersRow) -> f64 {
+        match player.num_games {
+            i if i < K_FACTOR_LIMIT => K_FACTOR_PROVISIONAL,
+            _ => K_FACTOR_STANDARD,
+        }
+    }
+}
+
+impl RatingSystem for Elo {
+    fn rating_column(&self) -> &'static str {
+        "elo"
+    }
+
+    fn rating(&self, player: &PlayersRow) -> f64 {
+        player.elo
+    }
+
     /// Calculates the actual elo changes given two players and their respective
     /// scores in a set. This will only be called when both player's finish
     /// a set completely.
-    pub fn calc_elo(&mut self) -> (f64, f64) {
-        // Calculate the k-factor for each player. If the player has played
-        // under 20 total games for any game, they are assigned provisional.
-        let k_factor_one = match self.player_one.num_games {
-            i if i < K_FACTOR_LIMIT => K_FACTOR_PROVISIONAL,
-            _ => K_FACTOR_STANDARD,
-        };
-        let k_factor_two = match self.player_two.num_games {
-            i if i < K_FACTOR_LIMIT => K_FACTOR_PROVISIONAL,
-            _ => K_FACTOR_STANDARD,
-        };
+    fn rate_set(
+        &mut self,
+        player_one: &PlayersRow,
+        player_two: &PlayersRow,
+        result: &SetResult,
+    ) -> RatingChange {
+        let k_factor_one = Elo::k_factor(player_one);
+        let k_factor_two = Elo::k_factor(player_two);
 
         // Calculate the change in elo for both players. The formula for a
         // player's change in elo is given as
         //
         // delta = k_factor * (score - ex_score)
-        let num_games = self.score_one + self.score_two;
-        let (ex_score_one, ex_score_two) = self.expected_scores(num_games);
-        let delta_one = k_factor_one * (self.score_one as f64 - ex_score_one);
-        let delta_two = k_factor_two * (self.score_two as f64 - ex_score_two);
+        let num_games = result.score_one + result.score_two;
+        let (ex_score_one, ex_score_two) =
+            Elo::expected_scores(player_one.elo, player_two.elo, num_games);
+        let delta_one = k_factor_one * (result.score_one as f64 - ex_score_one);
+        let delta_two = k_factor_two * (result.score_two as f64 - ex_score_two);
 
-        self.player_one.elo += delta_one;
-        self.player_one.num_games += self.score_one + self.score_two;
-        self.player_one.wins += self.score_one;
-        self.player_one.losses += self.score_two;
-        self.player_one.win_loss_ratio =
-            self.player_one.wins as f64 / self.player_one.num_games as f64;
+        let mut player_one = player_one.clone();
+        let mut player_two = player_two.clone();
+        player_one.elo += delta_one;
+        player_two.elo += delta_two;
 
-        self.player_two.elo += delta_two;
-        self.player_two.num_games += self.score_one + self.score_two;
-        self.player_two.wins += self.score_two;
-        self.player_two.losses += self.score_one;
-        self.player_two.win_loss_ratio =
-            self.player_two.wins as f64 / self.player_two.num_games as f64;
-
-        (delta_one, delta_two)
+        RatingChange {
+            player_one,
+            player_two,
+            delta_one,
+            delta_two,
+        }
     }
 }
@@ -0,0 +1,252 @@
+use crate::cli;
+use std::fmt;
+
+/// Every way the program can fail. Errors are passed up to `main`, which
+/// prints them and exits with the matching status code.
+#[derive(Debug)]
+pub enum Error {
+    /// The request to smash.gg could not be sent or its response read.
+    Api(reqwest::Error),
+    /// smash.gg answered the request with an error.
+    Response(ApiError),
+    /// A field expected in smash.gg's response was missing.
+    MissingField(&'static str),
+    /// Reading or writing the sqlite database failed.
+    Database(rusqlite::Error),
+    /// Reading or writing a file failed.
+    Io(std::io::Error),
+    /// A value was neither given on the command line nor could be prompted
+    /// for because no terminal is attached.
+    MissingInput(&'static str),
+    /// A value given by the user can't be used.
+    InvalidInput(String),
+    /// Some of the tournaments or events of a batch failed to import. Each
+    /// failure has already been reported.
+    Batch(usize),
+    /// Another error, along with what was being done when it happened.
+    Context(String, Box<Error>),
+}
+
+impl Error {
+    /// The status code the program exits with because of this error.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Api(_) | Error::Response(_) | Error::MissingField(_) => {
+                cli::EXIT_API
+            }
+            Error::Database(_) => cli::EXIT_DATABASE,
+            Error::Io(_) | Error::Batch(_) => cli::EXIT_FAILURE,
+            Error::MissingInput(_) | Error::InvalidInput(_) => cli::EXIT_USAGE,
+            Error::Context(_, source) => source.exit_code(),
+        }
+    }
+
+    /// Returns true if sending the same request again may succeed, such as
+    /// after a network failure, the rate limit or a server error.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Api(err) => {
+                err.is_timeout() || err.is_connect() || err.is_request()
+            }
+            Error::Response(err) => {
+                err.kind == ApiErrorKind::RateLimit || err.status >= 500
+            }
+            Error::Context(_, source) => source.is_transient(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if smash.gg refused a request because the query would
+    /// return too many objects, in which case asking for fewer at a time
+    /// may succeed.
+    pub fn is_complexity(&self) -> bool {
+        match self {
+            Error::Response(err) => err.kind == ApiErrorKind::Complexity,
+            Error::Context(_, source) => source.is_complexity(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Api(err) => write!(f, "Request to smash.gg failed: {}", err),
+            Error::Response(err) => write!(f, "{}", err),
+            Error::MissingField(field) => {
+                write!(f, "No {} found in smash.gg's response", field)
+            }
+            Error::Database(err) => write!(f, "Database error: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Batch(failed) => {
+                write!(f, "{} tournaments or events of the batch failed", failed)
+            }
+            Error::MissingInput(input) => write!(f, "Missing input: {}", input),
+            Error::InvalidInput(message) => write!(f, "{}", message),
+            Error::Context(context, source) => {
+                write!(f, "While {}:\n  {}", context, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Api(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Error::Database(err)
+    }
+}
+
+/// The kinds of errors smash.gg answers requests with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// The auth token is missing, invalid or expired.
+    Auth,
+    /// The query asked for too many objects at once.
+    Complexity,
+    /// Too many requests were sent in too short a time.
+    RateLimit,
+    /// The tournament or event asked for doesn't exist.
+    NotFound,
+    Other,
+}
+
+/// An error reported by smash.gg, either through the HTTP status of the
+/// response or the GraphQL `errors` array in its body.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub kind: ApiErrorKind,
+    pub messages: Vec<String>,
+}
+
+impl ApiError {
+    /// Classifies an error from the HTTP status and the messages returned.
+    /// smash.gg doesn't give error codes, so the messages are searched for
+    /// what went wrong when the status doesn't tell.
+    pub fn new(status: u16, messages: Vec<String>) -> Self {
+        let text = messages.join(" ").to_lowercase();
+        let kind = match status {
+            401 | 403 => ApiErrorKind::Auth,
+            429 => ApiErrorKind::RateLimit,
+            404 => ApiErrorKind::NotFound,
+            _ if text.contains("rate limit") => ApiErrorKind::RateLimit,
+            _ if text.contains("complexity") => ApiErrorKind::Complexity,
+            _ if mentions_auth(&text) => ApiErrorKind::Auth,
+            _ if text.contains("not found") => ApiErrorKind::NotFound,
+            _ => ApiErrorKind::Other,
+        };
+
+        ApiError {
+            status,
+            kind,
+            messages,
+        }
+    }
+}
+
+// Whether an error message is about the auth token. Whole words are
+// matched, so words like "author" don't count.
+fn mentions_auth(text: &str) -> bool {
+    const AUTH_WORDS: [&str; 8] = [
+        "auth",
+        "authentication",
+        "authenticated",
+        "unauthenticated",
+        "authorization",
+        "authorized",
+        "unauthorized",
+        "token",
+    ];
+
+    text.split(|c: char| !c.is_alphanumeric())
+        .any(|word| AUTH_WORDS.contains(&word))
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let summary = match self.kind {
+            ApiErrorKind::Auth => "smash.gg rejected the auth token",
+            ApiErrorKind::Complexity => "smash.gg refused the query as too complex",
+            ApiErrorKind::RateLimit => "smash.gg's rate limit was exceeded",
+            ApiErrorKind::NotFound => "Not found on smash.gg",
+            ApiErrorKind::Other => "smash.gg returned an error",
+        };
+        write!(
+            f,
+            "{} (HTTP {}): {}",
+            summary,
+            self.status,
+            self.messages.join("; ")
+        )?;
+
+        match self.kind {
+            ApiErrorKind::Auth => write!(f, "\n  Check that the auth token is valid"),
+            ApiErrorKind::RateLimit => {
+                write!(f, "\n  Wait a minute before trying again")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Adds a description of what was being done to the error of a result.
+pub trait Context<T> {
+    fn context<F: FnOnce() -> String>(self, context: F) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+    fn context<F: FnOnce() -> String>(self, context: F) -> Result<T, Error> {
+        self.map_err(|err| Error::Context(context(), Box::new(err.into())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_errors_by_status_then_message() {
+        let cases = [
+            (401, "", ApiErrorKind::Auth),
+            (403, "rate limit exceeded", ApiErrorKind::Auth),
+            (429, "", ApiErrorKind::RateLimit),
+            (404, "", ApiErrorKind::NotFound),
+            (200, "Rate limit exceeded - api-token", ApiErrorKind::RateLimit),
+            (200, "Your query complexity is too high", ApiErrorKind::Complexity),
+            (200, "Invalid authentication token", ApiErrorKind::Auth),
+            (400, "Your token is not authorized", ApiErrorKind::Auth),
+            (200, "Auth: missing header", ApiErrorKind::Auth),
+            (200, "Tournament not found", ApiErrorKind::NotFound),
+            (200, "Unknown author of the event", ApiErrorKind::Other),
+            (500, "Internal server error", ApiErrorKind::Other),
+        ];
+
+        for (status, message, kind) in cases {
+            let err = ApiError::new(status, vec![message.to_owned()]);
+            assert_eq!(err.kind, kind, "{} {:?}", status, message);
+        }
+    }
+
+    #[test]
+    fn searches_every_message() {
+        let messages = vec![
+            "Something went wrong".to_owned(),
+            "Invalid token".to_owned(),
+        ];
+        assert_eq!(ApiError::new(200, messages).kind, ApiErrorKind::Auth);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::error::{Context, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether responses from smash.gg are recorded to a fixture directory or
+/// replayed from one instead of using the network.
+#[derive(Debug, Clone)]
+pub enum Fixtures {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+// A request sent to smash.gg and the response it got, as saved in a
+// fixture file. The body is kept as json when it is json so fixtures can be
+// read and edited by hand.
+#[derive(Serialize, Deserialize)]
+struct Fixture {
+    query: String,
+    variables: Value,
+    status: u16,
+    body: Value,
+}
+
+/// Saves the response to a request in the fixture directory, replacing any
+/// response recorded for the same request before.
+pub fn record(
+    dir: &Path,
+    key: &str,
+    query: &str,
+    variables: &Value,
+    status: u16,
+    body: &str,
+) -> Result<(), Error> {
+    let fixture = Fixture {
+        query: query.to_owned(),
+        variables: variables.clone(),
+        status,
+        body: serde_json::from_str(body)
+            .unwrap_or_else(|_| Value::String(body.to_owned())),
+    };
+    let path = dir.join(file_name(query, key));
+
+    fs::create_dir_all(dir)
+        .and_then(|()| Ok(serde_json::to_string_pretty(&fixture)?))
+        .and_then(|contents| fs::write(&path, contents))
+        .context(|| format!("recording the fixture {}", path.display()))
+}
+
+/// Reads the response recorded for a request from the fixture directory,
+/// returning its HTTP status and body.
+pub fn replay(
+    dir: &Path,
+    key: &str,
+    query: &str,
+) -> Result<(u16, String), Error> {
+    let path = dir.join(file_name(query, key));
+    if !path.exists() {
+        return Err(Error::InvalidInput(format!(
+            "No fixture recorded for this {} request: {} doesn't exist",
+            operation_name(query),
+            path.display()
+        )));
+    }
+
+    let contents = fs::read_to_string(&path)
+        .context(|| format!("reading the fixture {}", path.display()))?;
+    let fixture: Fixture = serde_json::from_str(&contents).map_err(|err| {
+        Error::InvalidInput(format!(
+            "Invalid fixture {}: {}",
+            path.display(),
+            err
+        ))
+    })?;
+
+    let body = match fixture.body {
+        Value::String(body) => body,
+        body => body.to_string(),
+    };
+    Ok((fixture.status, body))
+}
+
+// Fixtures are named after the query they answer, followed by part of the
+// request's key, so the directory can be browsed by hand.
+fn file_name(query: &str, key: &str) -> String {
+    format!("{}-{}.json", operation_name(query), &key[..key.len().min(16)])
+}
+
+// The name of a GraphQL operation, as in query [name](...) { ... }.
+fn operation_name(query: &str) -> &str {
+    query
+        .trim_start()
+        .trim_start_matches("query")
+        .trim_start()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("query")
+}
@@ -0,0 +1,284 @@
+use crate::rating::{RatingChange, RatingSystem, SetResult};
+use crate::PlayersRow;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+pub const DEFAULT_RATING: f64 = 1500.0;
+pub const DEFAULT_RD: f64 = 350.0;
+const SCALE: f64 = 173.7178;
+const TAU: f64 = 0.5;
+const EPSILON: f64 = 0.000001;
+
+/// A player's Glicko-2 state on the original Glicko scale.
+#[derive(Debug, Clone, Copy)]
+pub struct GlickoRating {
+    pub rating: f64,
+    pub rd: f64,
+    pub volatility: f64,
+}
+
+// A single game played during a rating period against an opponent whose
+// rating is fixed at the start of the period.
+struct GameResult {
+    opponent_mu: f64,
+    opponent_phi: f64,
+    score: f64,
+}
+
+/// Accumulates every game played in a rating period and computes the new
+/// Glicko-2 ratings once the period ends. A tournament is treated as one
+/// rating period, so ratings used for expected scores don't move until every
+/// event of the tournament has been recorded.
+#[derive(Default)]
+pub struct Glicko2 {
+    ratings: HashMap<i32, GlickoRating>,
+    results: HashMap<i32, Vec<GameResult>>,
+}
+
+impl Glicko2 {
+    pub fn new() -> Self {
+        Glicko2::default()
+    }
+
+    /// Records the result of a set between two players. Each game of the set
+    /// counts as one result, mirroring how the Elo calculation works. The
+    /// first rating seen for a player is kept as their pre-period rating.
+    fn record_set(
+        &mut self,
+        player_one: (i32, GlickoRating),
+        score_one: i32,
+        player_two: (i32, GlickoRating),
+        score_two: i32,
+    ) {
+        let rating_one = *self.ratings.entry(player_one.0).or_insert(player_one.1);
+        let rating_two = *self.ratings.entry(player_two.0).or_insert(player_two.1);
+
+        for (score, result_one, result_two) in
+            [(score_one, 1.0, 0.0), (score_two, 0.0, 1.0)]
+        {
+            for _games in 0..score {
+                self.results.entry(player_one.0).or_default().push(GameResult {
+                    opponent_mu: to_mu(rating_two.rating),
+                    opponent_phi: to_phi(rating_two.rd),
+                    score: result_one,
+                });
+                self.results.entry(player_two.0).or_default().push(GameResult {
+                    opponent_mu: to_mu(rating_one.rating),
+                    opponent_phi: to_phi(rating_one.rd),
+                    score: result_two,
+                });
+            }
+        }
+    }
+
+    /// Ends the rating period and returns the new rating of every player that
+    /// played in it. Clears the recorded games for the next period.
+    fn finish(&mut self) -> HashMap<i32, GlickoRating> {
+        let mut new_ratings = HashMap::new();
+
+        for (global_id, rating) in self.ratings.drain() {
+            let new_rating = match self.results.get(&global_id) {
+                Some(results) => rate(&rating, results),
+                None => inactive(&rating),
+            };
+            new_ratings.insert(global_id, new_rating);
+        }
+        self.results.clear();
+
+        new_ratings
+    }
+}
+
+impl RatingSystem for Glicko2 {
+    fn rating_column(&self) -> &'static str {
+        "glicko_rating"
+    }
+
+    fn rating(&self, player: &PlayersRow) -> f64 {
+        player.glicko_rating
+    }
+
+    /// Records the set for the current rating period. Ratings don't change
+    /// until the period ends, so both deltas are zero here and the change
+    /// over the whole period is recorded on the set once it ends.
+    fn rate_set(
+        &mut self,
+        player_one: &PlayersRow,
+        player_two: &PlayersRow,
+        result: &SetResult,
+    ) -> RatingChange {
+        self.record_set(
+            (player_one.global_id, player_one.glicko()),
+            result.score_one,
+            (player_two.global_id, player_two.glicko()),
+            result.score_two,
+        );
+
+        RatingChange {
+            player_one: player_one.clone(),
+            player_two: player_two.clone(),
+            delta_one: 0.0,
+            delta_two: 0.0,
+        }
+    }
+
+    fn has_rating_periods(&self) -> bool {
+        true
+    }
+
+    /// Players who competed get their new rating, and everyone else becomes
+    /// less certain.
+    fn end_rating_period(&mut self, players: &mut [PlayersRow]) {
+        let new_ratings = self.finish();
+
+        for player in players {
+            let new_rating = match new_ratings.get(&player.global_id) {
+                Some(new_rating) => *new_rating,
+                None => inactive(&player.glicko()),
+            };
+            player.set_glicko(&new_rating);
+        }
+    }
+}
+
+/// Increases the rating deviation of a player that did not compete in a
+/// rating period. Their rating and volatility are unchanged.
+fn inactive(player: &GlickoRating) -> GlickoRating {
+    let phi = to_phi(player.rd);
+    let phi_star = (phi.powi(2) + player.volatility.powi(2)).sqrt();
+
+    GlickoRating {
+        rating: player.rating,
+        rd: (phi_star * SCALE).min(DEFAULT_RD),
+        volatility: player.volatility,
+    }
+}
+
+// Steps 3 through 8 of the Glicko-2 algorithm as described in
+// http://www.glicko.net/glicko/glicko2.pdf
+fn rate(player: &GlickoRating, results: &[GameResult]) -> GlickoRating {
+    let mu = to_mu(player.rating);
+    let phi = to_phi(player.rd);
+
+    // Estimated variance of the player's rating based on game outcomes, and
+    // the estimated improvement in rating.
+    let (mut v_inv, mut delta_sum) = (0.0, 0.0);
+    for result in results {
+        let g_phi = g(result.opponent_phi);
+        let expected = expected_score(mu, result.opponent_mu, g_phi);
+        v_inv += g_phi.powi(2) * expected * (1.0 - expected);
+        delta_sum += g_phi * (result.score - expected);
+    }
+    let v = 1.0 / v_inv;
+    let delta = v * delta_sum;
+
+    let volatility = new_volatility(phi, player.volatility, v, delta);
+    let phi_star = (phi.powi(2) + volatility.powi(2)).sqrt();
+    let new_phi = 1.0 / (1.0 / phi_star.powi(2) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi.powi(2) * delta_sum;
+
+    GlickoRating {
+        rating: new_mu * SCALE + DEFAULT_RATING,
+        rd: new_phi * SCALE,
+        volatility,
+    }
+}
+
+// Determines the new volatility using the Illinois algorithm.
+fn new_volatility(phi: f64, sigma: f64, v: f64, delta: f64) -> f64 {
+    let a = sigma.powi(2).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        let denom = phi.powi(2) + v + ex;
+        ex * (delta.powi(2) - phi.powi(2) - v - ex) / (2.0 * denom.powi(2))
+            - (x - a) / TAU.powi(2)
+    };
+
+    let mut big_a = a;
+    let mut big_b = if delta.powi(2) > phi.powi(2) + v {
+        (delta.powi(2) - phi.powi(2) - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * TAU) < 0.0 {
+            k += 1.0;
+        }
+        a - k * TAU
+    };
+
+    let mut f_a = f(big_a);
+    let mut f_b = f(big_b);
+    while (big_b - big_a).abs() > EPSILON {
+        let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+        let f_c = f(big_c);
+        if f_c * f_b <= 0.0 {
+            big_a = big_b;
+            f_a = f_b;
+        } else {
+            f_a /= 2.0;
+        }
+        big_b = big_c;
+        f_b = f_c;
+    }
+
+    (big_a / 2.0).exp()
+}
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi.powi(2) / PI.powi(2)).sqrt()
+}
+
+fn expected_score(mu: f64, opponent_mu: f64, g_phi: f64) -> f64 {
+    1.0 / (1.0 + (-g_phi * (mu - opponent_mu)).exp())
+}
+
+fn to_mu(rating: f64) -> f64 {
+    (rating - DEFAULT_RATING) / SCALE
+}
+
+fn to_phi(rd: f64) -> f64 {
+    rd / SCALE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glicko(rating: f64, rd: f64, volatility: f64) -> GlickoRating {
+        GlickoRating {
+            rating,
+            rd,
+            volatility,
+        }
+    }
+
+    // The worked example of section 4 of Glickman's paper: a 1500 player
+    // beats a 1400 player, then loses to a 1550 and a 1700 player.
+    #[test]
+    fn rates_the_example_from_the_paper() {
+        let results: Vec<GameResult> =
+            [(1400.0, 30.0, 1.0), (1550.0, 100.0, 0.0), (1700.0, 300.0, 0.0)]
+                .into_iter()
+                .map(|(rating, rd, score)| GameResult {
+                    opponent_mu: to_mu(rating),
+                    opponent_phi: to_phi(rd),
+                    score,
+                })
+                .collect();
+
+        let new_rating = rate(&glicko(1500.0, 200.0, 0.06), &results);
+        assert!((new_rating.rating - 1464.06).abs() < 0.01, "{:?}", new_rating);
+        assert!((new_rating.rd - 151.52).abs() < 0.01, "{:?}", new_rating);
+        assert!((new_rating.volatility - 0.05999).abs() < 0.00001, "{:?}", new_rating);
+    }
+
+    #[test]
+    fn only_widens_the_deviation_of_inactive_players() {
+        let player = glicko(1700.0, 100.0, 0.06);
+        let new_rating = inactive(&player);
+
+        assert_eq!(new_rating.rating, player.rating);
+        assert_eq!(new_rating.volatility, player.volatility);
+        assert!(new_rating.rd > player.rd && new_rating.rd < 101.0);
+        assert_eq!(inactive(&glicko(1500.0, DEFAULT_RD, 0.06)).rd, DEFAULT_RD);
+    }
+}
@@ -0,0 +1,1045 @@
+use crate::cli::{IngestArgs, RatingArgs, RatingKind};
+use crate::error::{Context, Error};
+use crate::json::{self, EntrantInfo, EntrantMap, EventInfo, EventSelection};
+use crate::rating::{self, RatingSystem};
+use crate::recompute;
+use crate::reqwest_wrapper::{ClientOptions, Content, ContentType, ReqwestClient};
+use crate::rusqlite_wrapper::{EventsRow, PlayersRow, RusqliteConnection, SetsRow};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::ValueEnum;
+use smashgg_elo::{clean_string, prompt_input};
+use std::collections::HashMap;
+
+pub const PLAYERS: &str = "players";
+pub const DOUBLES: &str = "doubles";
+const RATING_SETTING: &str = "rating_system";
+const DOUBLES_SETTING: &str = "doubles_in_singles";
+const AUTH_PROMPT: &str = "See info about authentication here: https://developer.start.gg/docs/authentication\nEnter your smash.gg authentication token: ";
+const SLUG_PROMPT: &str = "A tournament slug is of the form: https://start.gg/tournament/[tournament slug]/...\nEnter the tournament slug or URL to read data from: ";
+
+/// Reads the auth token from the given environment variable, falling back to
+/// prompting the user if a terminal is attached.
+fn resolve_token(token_env: &str) -> Result<String, Error> {
+    match std::env::var(token_env) {
+        Ok(token) if !token.trim().is_empty() => Ok(token),
+        _ => prompt_input(AUTH_PROMPT).ok_or(Error::MissingInput("auth token")),
+    }
+}
+
+/// Creates the client used to send requests to smash.gg, resolving the auth
+/// token first. No token is needed when responses are replayed from
+/// fixtures.
+pub fn connect(
+    token_env: &str,
+    client_options: ClientOptions,
+) -> Result<ReqwestClient<'static>, Error> {
+    let auth_token = match client_options.replays() {
+        true => String::new(),
+        false => resolve_token(token_env)?,
+    };
+    ReqwestClient::new(&auth_token, client_options)
+}
+
+/// Determines the rating systems of the database. The choice is saved the
+/// first time a database is used and can't be changed afterwards, since the
+/// ratings already recorded were calculated with it. Databases from before
+/// the choice was saved have always used Elo. Whether doubles are rated in
+/// the singles tables is saved whenever it is given.
+pub fn resolve_rating_kinds(
+    rusqlite_connection: &RusqliteConnection,
+    ratings: RatingArgs,
+) -> Result<Vec<RatingKind>, Error> {
+    if let Some(doubles_in_singles) = ratings.doubles_in_singles {
+        rusqlite_connection
+            .set_setting(DOUBLES_SETTING, &doubles_in_singles.to_string())?;
+    }
+
+    let requested = ratings.rating_system;
+    let saved = match rusqlite_connection.get_setting(RATING_SETTING)? {
+        Some(saved) => saved
+            .split(',')
+            .map(|kind| {
+                RatingKind::from_str(kind, true).map_err(|_| {
+                    Error::InvalidInput(format!(
+                        "Unknown rating system {:?} in database",
+                        kind
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None if rusqlite_connection.has_sets()? => vec![RatingKind::Elo],
+        None => Vec::new(),
+    };
+
+    match (saved.is_empty(), requested.is_empty()) {
+        (false, false) if saved != requested => Err(Error::InvalidInput(format!(
+            "Database uses the {} rating system, not {}",
+            join_kinds(&saved),
+            join_kinds(&requested)
+        ))),
+        (false, _) => Ok(saved),
+        (true, true) => {
+            save_rating_kinds(rusqlite_connection, &[RatingKind::Elo])?;
+            Ok(vec![RatingKind::Elo])
+        }
+        (true, false) => {
+            save_rating_kinds(rusqlite_connection, &requested)?;
+            Ok(requested)
+        }
+    }
+}
+
+/// Saves the rating systems a database uses in its settings table.
+pub fn save_rating_kinds(
+    rusqlite_connection: &RusqliteConnection,
+    kinds: &[RatingKind],
+) -> Result<(), Error> {
+    rusqlite_connection.set_setting(RATING_SETTING, &join_kinds(kinds))?;
+    Ok(())
+}
+
+// Whether sets between teams are also rated in the singles tables, as saved
+// in the database. They are kept apart unless asked otherwise.
+fn doubles_in_singles(
+    rusqlite_connection: &RusqliteConnection,
+) -> Result<bool, Error> {
+    let setting = rusqlite_connection.get_setting(DOUBLES_SETTING)?;
+    Ok(setting.as_deref() == Some("true"))
+}
+
+/// The player tables an event is rated in: the global table followed by the
+/// table of its game. Teams are rated in tables of their own, so doubles
+/// never affect singles ratings.
+pub fn rating_tables(game_name: &str, is_team: bool) -> [String; 2] {
+    match is_team {
+        false => [PLAYERS.to_owned(), game_name.to_owned()],
+        true => [DOUBLES.to_owned(), format!("{} doubles", game_name)],
+    }
+}
+
+/// Every table that individual players are rated in, leaving out the tables
+/// of teams.
+pub fn player_tables(
+    rusqlite_connection: &RusqliteConnection,
+) -> Result<Vec<String>, Error> {
+    let mut team_tables = Vec::new();
+    for (game_name, is_team) in rusqlite_connection.select_game_names()? {
+        if is_team {
+            team_tables.extend(rating_tables(&game_name, true).map(|table| {
+                clean_string(&table)
+            }));
+        }
+    }
+
+    let mut tables = rusqlite_connection.select_rating_tables()?;
+    tables.retain(|table| !team_tables.contains(table));
+    Ok(tables)
+}
+
+fn join_kinds(kinds: &[RatingKind]) -> String {
+    kinds
+        .iter()
+        .map(|kind| kind.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Everything downloaded about an event that is needed to record it.
+pub struct EventData {
+    pub tournament_slug: String,
+    pub event_id: i32,
+    pub event_name: String,
+    pub game_name: String,
+    pub game_id: i32,
+    pub players: EntrantMap,
+    /// Every set of the event, sorted by the time it was completed.
+    pub sets: Vec<json::SetInfo>,
+}
+
+/// Returns true if either player was disqualified from the set.
+pub fn is_dq(set: &SetsRow) -> bool {
+    set.player_one_score == -1 || set.player_two_score == -1
+}
+
+/// The rating period of a tournament. Every event of a tournament is rated
+/// by the same rating systems, kept here by table, and the period ends once
+/// the whole tournament has been rated. Systems like Glicko-2 then update
+/// each player once however many of its events they entered, and players
+/// who sat the tournament out become less certain only once.
+pub struct RatingPeriod {
+    rating_kinds: Vec<RatingKind>,
+    systems: HashMap<String, Vec<Box<dyn RatingSystem>>>,
+    sets: Vec<SetsRow>,
+}
+
+impl RatingPeriod {
+    pub fn new(rating_kinds: &[RatingKind]) -> Self {
+        RatingPeriod {
+            rating_kinds: rating_kinds.to_vec(),
+            systems: HashMap::new(),
+            sets: Vec::new(),
+        }
+    }
+
+    /// Keeps a rated set that has been saved, so the change in rating of
+    /// both players can be recorded on it if the primary rating system
+    /// only updates ratings once the period ends.
+    pub fn record_set(&mut self, set: &SetsRow) {
+        self.sets.push(set.clone());
+    }
+
+    // Takes out the rating systems of a table, or fresh ones if nothing has
+    // been rated in it during the period yet.
+    fn take(&mut self, table_name: &str) -> Vec<Box<dyn RatingSystem>> {
+        self.systems
+            .remove(&clean_string(table_name))
+            .unwrap_or_else(|| rating::build_systems(&self.rating_kinds))
+    }
+
+    // Gives back the rating systems of a table once an event is rated.
+    fn give_back(&mut self, table_name: &str, systems: Vec<Box<dyn RatingSystem>>) {
+        self.systems.insert(clean_string(table_name), systems);
+    }
+
+    /// Ends the period in every table rated during it. Systems that wait
+    /// for the period to end calculate their new ratings, and the tables
+    /// are ranked again.
+    pub fn end(&mut self, rusqlite_connection: &RusqliteConnection) -> Result<(), Error> {
+        let mut tables = Vec::new();
+        let mut deltas = HashMap::new();
+        for (table, mut systems) in self.systems.drain() {
+            let table_deltas =
+                end_rating_periods(rusqlite_connection, &mut systems, &table)?;
+            deltas.insert(table.clone(), table_deltas);
+            tables.push(table);
+        }
+
+        // Sets of a primary system that waits for the period to end were
+        // recorded without a change in rating. Each player's change over
+        // the whole period is recorded on their sets instead.
+        let sets = std::mem::take(&mut self.sets);
+        if rating::build_systems(&self.rating_kinds)[0].has_rating_periods() {
+            for mut set in sets {
+                let table =
+                    clean_string(&rating_tables(&set.game_name, set.is_team)[0]);
+                let delta = |global_id| {
+                    deltas
+                        .get(&table)
+                        .and_then(|table_deltas| table_deltas.get(&global_id))
+                        .copied()
+                        .unwrap_or_default()
+                };
+                set.player_one_elo_delta = delta(set.player_one_global_id);
+                set.player_two_elo_delta = delta(set.player_two_global_id);
+                rusqlite_connection.update_set_ratings(&set)?;
+            }
+        }
+
+        update_rankings(rusqlite_connection, &self.rating_kinds, &tables)
+    }
+
+    /// Forgets everything rated during the period without ending it, for
+    /// when every event has just been replayed, this tournament included.
+    pub fn discard(&mut self) {
+        self.systems.clear();
+        self.sets.clear();
+    }
+}
+
+/// Ends the rating period of a tournament in its own transaction.
+pub fn end_period(
+    rusqlite_connection: &RusqliteConnection,
+    period: &mut RatingPeriod,
+) -> Result<(), Error> {
+    rusqlite_connection
+        .transaction(|| period.end(rusqlite_connection))
+        .context(|| "ending the rating period of the tournament".to_string())
+}
+
+/// The rating systems run over one event, with separate instances for the
+/// global table and the table of the event's game. When doubles are rated in
+/// the singles tables, a team event also rates each member there.
+pub struct EventRating {
+    tables: [String; 2],
+    global_systems: Vec<Box<dyn RatingSystem>>,
+    game_systems: Vec<Box<dyn RatingSystem>>,
+    singles: Option<Box<EventRating>>,
+}
+
+impl EventRating {
+    /// Creates the tables for the event's ratings if needed, and takes the
+    /// rating systems of the tournament's rating period for them.
+    pub fn new(
+        rusqlite_connection: &RusqliteConnection,
+        period: &mut RatingPeriod,
+        game_name: &str,
+        is_team: bool,
+    ) -> Result<Self, Error> {
+        let tables = rating_tables(game_name, is_team);
+        for table in &tables {
+            rusqlite_connection.create_table(table)?;
+        }
+
+        let singles = match is_team && doubles_in_singles(rusqlite_connection)? {
+            true => Some(Box::new(EventRating::new(
+                rusqlite_connection,
+                period,
+                game_name,
+                false,
+            )?)),
+            false => None,
+        };
+
+        let [global_table, game_table] = &tables;
+        Ok(EventRating {
+            global_systems: period.take(global_table),
+            game_systems: period.take(game_table),
+            tables,
+            singles,
+        })
+    }
+
+    /// Every table the event's ratings are recorded in.
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables = self.tables.to_vec();
+        if let Some(singles) = &self.singles {
+            tables.extend(singles.tables());
+        }
+        tables
+    }
+
+    /// Rates a completed set in both the global and game table. The ratings
+    /// going in and the change in the primary rating are recorded on the
+    /// set, and both players' updated stats are saved. Returns the players'
+    /// updated rows in the game table.
+    pub fn rate_set(
+        &mut self,
+        rusqlite_connection: &RusqliteConnection,
+        set: &mut SetsRow,
+    ) -> Result<(PlayersRow, PlayersRow), Error> {
+        let [global_table, game_table] = &self.tables;
+
+        // Select both players from the global players table and the
+        // game table in the sqlite database.
+        let global_player_one = rusqlite_connection.select_player(
+            set.player_one_global_id,
+            &set.player_one_name,
+            global_table,
+        )?;
+        let global_player_two = rusqlite_connection.select_player(
+            set.player_two_global_id,
+            &set.player_two_name,
+            global_table,
+        )?;
+        let game_player_one = rusqlite_connection.select_player(
+            set.player_one_global_id,
+            &set.player_one_name,
+            game_table,
+        )?;
+        let game_player_two = rusqlite_connection.select_player(
+            set.player_two_global_id,
+            &set.player_two_name,
+            game_table,
+        )?;
+
+        // Record the rating before the change
+        set.player_one_elo = self.global_systems[0].rating(&global_player_one);
+        set.player_two_elo = self.global_systems[0].rating(&global_player_two);
+
+        // Calculate the new ratings for both players in the global
+        // table and the game table.
+        let result = rating::SetResult {
+            score_one: set.player_one_score,
+            score_two: set.player_two_score,
+        };
+        let global_change = rating::rate_set(
+            &mut self.global_systems,
+            global_player_one,
+            global_player_two,
+            &result,
+        );
+        let game_change = rating::rate_set(
+            &mut self.game_systems,
+            game_player_one,
+            game_player_two,
+            &result,
+        );
+
+        // Record the change in rating.
+        set.player_one_elo_delta = global_change.delta_one;
+        set.player_two_elo_delta = global_change.delta_two;
+
+        // Update any changes in the player's stats in both the global and
+        // game table.
+        rusqlite_connection.update_player(&global_change.player_one, global_table)?;
+        rusqlite_connection.update_player(&game_change.player_one, game_table)?;
+        rusqlite_connection.update_player(&global_change.player_two, global_table)?;
+        rusqlite_connection.update_player(&game_change.player_two, game_table)?;
+
+        // Members of teams are also rated on their own when doubles are
+        // rated in the singles tables.
+        if set.is_team {
+            self.rate_members(rusqlite_connection, set)?;
+        }
+
+        Ok((game_change.player_one, game_change.player_two))
+    }
+
+    // Rates every member of both teams of a set in the singles tables, if
+    // doubles are rated there. Each member is rated as if they had played
+    // the set alone against a player with the average ratings of the other
+    // team.
+    fn rate_members(
+        &mut self,
+        rusqlite_connection: &RusqliteConnection,
+        set: &SetsRow,
+    ) -> Result<(), Error> {
+        let singles = match &mut self.singles {
+            Some(singles) => singles,
+            None => return Ok(()),
+        };
+        let team_one =
+            rusqlite_connection.select_team_members(set.player_one_global_id)?;
+        let team_two =
+            rusqlite_connection.select_team_members(set.player_two_global_id)?;
+        let result_one = rating::SetResult {
+            score_one: set.player_one_score,
+            score_two: set.player_two_score,
+        };
+        let result_two = rating::SetResult {
+            score_one: set.player_two_score,
+            score_two: set.player_one_score,
+        };
+
+        let EventRating {
+            tables: [global_table, game_table],
+            global_systems,
+            game_systems,
+            ..
+        } = &mut **singles;
+        for (table, systems) in
+            [(global_table, global_systems), (game_table, game_systems)]
+        {
+            // Both averages are taken before anyone's rating changes.
+            let average_one = team_average(
+                rusqlite_connection,
+                set.player_one_global_id,
+                &team_one,
+                table,
+            )?;
+            let average_two = team_average(
+                rusqlite_connection,
+                set.player_two_global_id,
+                &team_two,
+                table,
+            )?;
+
+            for (members, opponent, result) in [
+                (&team_one, &average_two, &result_one),
+                (&team_two, &average_one, &result_two),
+            ] {
+                for (name, global_id) in members {
+                    let member =
+                        rusqlite_connection.select_player(*global_id, name, table)?;
+                    let change =
+                        rating::rate_set(systems, member, opponent.clone(), result);
+                    rusqlite_connection.update_player(&change.player_one, table)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The player's rating in the game table by the primary rating system.
+    pub fn game_rating(&self, player: &PlayersRow) -> f64 {
+        self.game_systems[0].rating(player)
+    }
+
+    /// Wraps up the event once every set has been rated. The rating
+    /// systems go back to the tournament's rating period, everyone that
+    /// entered has their tournament count incremented, and the winner is
+    /// credited with the tournament win.
+    /// Tournament counts and wins of team members aren't recorded in the
+    /// singles tables.
+    pub fn finish(
+        self,
+        rusqlite_connection: &RusqliteConnection,
+        period: &mut RatingPeriod,
+        entrants: &[i32],
+        winner: Option<i32>,
+    ) -> Result<(), Error> {
+        for table in &self.tables {
+            if let Some(winner) = winner {
+                rusqlite_connection.assign_winner(winner, table)?;
+            }
+            rusqlite_connection.increment_count(entrants, table)?;
+        }
+
+        self.give_back(period);
+        Ok(())
+    }
+
+    fn give_back(self, period: &mut RatingPeriod) {
+        let [global_table, game_table] = &self.tables;
+        period.give_back(global_table, self.global_systems);
+        period.give_back(game_table, self.game_systems);
+
+        if let Some(singles) = self.singles {
+            singles.give_back(period);
+        }
+    }
+}
+
+// A stand-in for a team in a table of individual players, with the average
+// ratings and experience of its members. It takes an id no player has.
+fn team_average(
+    rusqlite_connection: &RusqliteConnection,
+    team_id: i32,
+    members: &[(String, i32)],
+    table_name: &str,
+) -> Result<PlayersRow, Error> {
+    let rows = members
+        .iter()
+        .map(|(name, global_id)| {
+            rusqlite_connection.select_player(*global_id, name, table_name)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = rows.len().max(1) as f64;
+    let mean = |value: fn(&PlayersRow) -> f64| {
+        rows.iter().map(value).sum::<f64>() / count
+    };
+
+    Ok(PlayersRow {
+        global_id: i32::MIN + team_id,
+        name: String::new(),
+        rank: 0,
+        elo: mean(|player| player.elo),
+        num_games: mean(|player| player.num_games as f64) as i32,
+        wins: 0,
+        losses: 0,
+        win_loss_ratio: 0.0,
+        num_tournaments: 0,
+        tournament_wins: 0,
+        glicko_rating: mean(|player| player.glicko_rating),
+        glicko_rd: mean(|player| player.glicko_rd),
+        glicko_volatility: mean(|player| player.glicko_volatility),
+    })
+}
+
+/// Ends the rating period of any rating system that only updates ratings
+/// once a tournament is over, and saves the new ratings of the table.
+/// Returns how much each player's rating changed by the primary system.
+fn end_rating_periods(
+    rusqlite_connection: &RusqliteConnection,
+    systems: &mut [Box<dyn RatingSystem>],
+    table_name: &str,
+) -> Result<HashMap<i32, f64>, Error> {
+    if !systems.iter().any(|system| system.has_rating_periods()) {
+        return Ok(HashMap::new());
+    }
+
+    let mut players = rusqlite_connection.select_players(table_name)?;
+    let ratings: Vec<f64> =
+        players.iter().map(|player| systems[0].rating(player)).collect();
+    for system in systems.iter_mut() {
+        system.end_rating_period(&mut players);
+    }
+    for player in &players {
+        rusqlite_connection.update_player(player, table_name)?;
+    }
+
+    Ok(players
+        .iter()
+        .zip(ratings)
+        .map(|(player, rating)| {
+            (player.global_id, systems[0].rating(player) - rating)
+        })
+        .collect())
+}
+
+/// Updates the rankings of the given player tables by the primary rating
+/// system.
+pub fn update_rankings(
+    rusqlite_connection: &RusqliteConnection,
+    rating_kinds: &[RatingKind],
+    tables: &[String],
+) -> Result<(), Error> {
+    let rating_column = rating::build_systems(rating_kinds)[0].rating_column();
+
+    for table in tables {
+        rusqlite_connection.update_ranking(table, rating_column)?;
+    }
+
+    Ok(())
+}
+
+/// The winner of an event's grand finals, the last set played. Whoever has
+/// the larger score won the tournament. Nobody is credited if it was a DQ.
+pub fn event_winner(last_set: Option<&SetsRow>) -> Option<i32> {
+    let set = last_set.filter(|set| !is_dq(set))?;
+
+    match set.player_one_score > set.player_two_score {
+        true => Some(set.player_one_global_id),
+        false => Some(set.player_two_global_id),
+    }
+}
+
+// The time a set was completed. A time out of range can't be ordered
+// against the other sets, so it is treated as missing.
+fn completed_at(time: i64) -> Result<DateTime<Utc>, Error> {
+    Utc.timestamp_opt(time, 0)
+        .single()
+        .ok_or(Error::MissingField("completion time of a set"))
+}
+
+/// Parses the chosen events of a tournament and records every set and the
+/// resulting rating changes in the database.
+pub fn ingest(
+    token_env: &str,
+    db_path: &str,
+    ratings: RatingArgs,
+    client_options: ClientOptions,
+    args: IngestArgs,
+) -> Result<(), Error> {
+    // Resolve everything we need from the user before touching the network.
+    let mut reqwest_client = connect(token_env, client_options)?;
+    let input = match args.slug.clone() {
+        Some(slug) => slug,
+        None => prompt_input(SLUG_PROMPT)
+            .ok_or(Error::MissingInput("tournament slug"))?,
+    };
+    let (slug, event_slug) = parse_tournament(&input);
+
+    // Init relevant objects
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+    let rating_kinds = resolve_rating_kinds(&rusqlite_connection, ratings)?;
+
+    // Grab the ids and names of the events we want to parse, then parse
+    // each in turn.
+    // The events are one rating period, which is ended even if one of them
+    // fails so the events recorded before it are fully rated.
+    let selection = args.event_selection(event_slug);
+    let events = fetch_events(&mut reqwest_client, &slug, &selection)?;
+    let mut period = RatingPeriod::new(&rating_kinds);
+    let ingested = events.iter().try_for_each(|event| {
+        ingest_event(
+            &mut reqwest_client,
+            &rusqlite_connection,
+            &mut period,
+            &slug,
+            event,
+            args.reimport,
+        )
+        .map(|_| ())
+    });
+    end_period(&rusqlite_connection, &mut period)?;
+    ingested?;
+
+    println!("Finished processing!");
+    Ok(())
+}
+
+/// Splits a tournament slug or a smash.gg or start.gg URL into the slug of
+/// the tournament and, if the URL points at an event, the slug of the event.
+/// Anything that isn't a URL is taken to be a tournament slug.
+pub fn parse_tournament(input: &str) -> (String, Option<String>) {
+    let input = input.trim();
+    let path = input
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(input)
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let segments: Vec<&str> =
+        path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    match segments.iter().position(|segment| *segment == "tournament") {
+        Some(index) if index + 1 < segments.len() => {
+            let event_slug = match segments.get(index + 2) {
+                Some(&"event") | Some(&"events") => {
+                    segments.get(index + 3).map(|slug| slug.to_string())
+                }
+                _ => None,
+            };
+            (segments[index + 1].to_owned(), event_slug)
+        }
+        _ => (input.trim_matches('/').to_owned(), None),
+    }
+}
+
+/// Lists the events of a tournament and picks out the ones selected.
+pub fn fetch_events(
+    reqwest_client: &mut ReqwestClient,
+    slug: &str,
+    selection: &EventSelection,
+) -> Result<Vec<EventInfo>, Error> {
+    let mut content = Content::new();
+    content.variables.tournament_slug = Some(slug.to_owned());
+    content.edit_content(ContentType::Init);
+    reqwest_client.construct_json(&content);
+
+    reqwest_client
+        .send_post()
+        .and_then(|json| json.get_events(selection))
+        .context(|| format!("reading the events of tournament {}", slug))
+}
+
+/// Whether an event was recorded or skipped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Imported,
+    Skipped,
+}
+
+/// Downloads every set of an event and records it in the database. Events
+/// that have already been imported are skipped, unless asked to import them
+/// again.
+/// The event is rated in the given rating period of its tournament, which
+/// the caller ends once every event of the tournament has been ingested.
+pub fn ingest_event(
+    reqwest_client: &mut ReqwestClient,
+    rusqlite_connection: &RusqliteConnection,
+    period: &mut RatingPeriod,
+    slug: &str,
+    info: &EventInfo,
+    reimport: bool,
+) -> Result<ImportOutcome, Error> {
+    let event_id = info.id;
+    let event_name = &info.name;
+    println!("Processing {} - {}...", info.game_name, event_name);
+
+    let reimport = match rusqlite_connection.select_event(event_id)? {
+        Some(event) if !reimport => {
+            println!(
+                "Skipping {} - {} from {}: already imported on {} with {} sets",
+                event.game_name,
+                event.event_name,
+                event.tournament_slug,
+                event.imported_at,
+                event.set_count
+            );
+            return Ok(ImportOutcome::Skipped);
+        }
+        Some(_) => true,
+        None => false,
+    };
+
+    // Responses about an event that has completed won't change, so they
+    // are cached forever. An event imported again is downloaded afresh, as
+    // its results may have been corrected since they were cached.
+    reqwest_client.set_cache_forever(info.is_completed());
+    reqwest_client.set_refresh(reimport);
+    let fetched = fetch_event(reqwest_client, info);
+    reqwest_client.set_cache_forever(false);
+    reqwest_client.set_refresh(false);
+    let (players, sets) = fetched?;
+
+    let event = EventData {
+        tournament_slug: slug.to_owned(),
+        event_id,
+        event_name: event_name.to_owned(),
+        game_name: info.game_name.to_owned(),
+        game_id: info.game_id,
+        players,
+        sets,
+    };
+
+    // Everything is recorded in one transaction, so a failure part way
+    // through leaves the database exactly as it was before the import.
+    rusqlite_connection.transaction(|| {
+        record_event(rusqlite_connection, period, &event, reimport)
+    })
+    .context(|| {
+        format!(
+            "recording {} - {} from {}",
+            event.game_name, event.event_name, event.tournament_slug
+        )
+    })?;
+
+    Ok(ImportOutcome::Imported)
+}
+
+// Downloads the entrants of an event, mapped from their tournament id to
+// their name and global id, and every set of the event sorted by the time
+// it was completed.
+fn fetch_event(
+    reqwest_client: &mut ReqwestClient,
+    info: &EventInfo,
+) -> Result<(EntrantMap, Vec<json::SetInfo>), Error> {
+    let event_id = info.id;
+    let event_name = &info.name;
+
+    // Create a mapping of players that participated in that event.
+    // The map is of the form key: tournament id, value: (name, global id).
+    println!("Constructing the list of players...");
+    let mut content = Content::new();
+    content.variables.event_id = Some(event_id);
+    content.edit_content(ContentType::Page);
+    let responses = reqwest_client
+        .send_all_pages(&content)
+        .context(|| format!("reading the entrants of {}", event_name))?;
+
+    let mut players = EntrantMap::new();
+    let num_pages = responses.len();
+    for (i, json) in (1..).zip(responses) {
+        println!("Processing page {} out of {}...", i, num_pages);
+        players.extend(json.get_entrants().context(|| {
+            format!(
+                "reading page {} of {} of entrants of {}",
+                i, num_pages, event_name
+            )
+        })?);
+    }
+
+    // Grab the paginated json for sets. Sort by the time completed.
+    println!("Requesting set data...");
+    content.edit_content(ContentType::Info);
+    let responses = reqwest_client
+        .send_all_pages(&content)
+        .context(|| format!("reading the sets of {}", event_name))?;
+
+    let mut set_list = Vec::<json::SetInfo>::new();
+    let num_pages = responses.len();
+    for (i, json) in (1..).zip(responses) {
+        println!("Processing page {} out of {}...", i, num_pages);
+        let mut set_unsorted_list = json.get_sets_info().context(|| {
+            format!("reading page {} of {} of sets of {}", i, num_pages, event_name)
+        })?;
+        set_list.append(&mut set_unsorted_list);
+    }
+    set_list.sort_unstable_by_key(|set| set.time);
+
+    Ok((players, set_list))
+}
+
+/// Records every set of a downloaded event in the database along with the
+/// resulting rating changes, and marks the event as imported. When the event
+/// is being imported again, its old sets are removed first and every event
+/// is replayed afterwards.
+fn record_event(
+    rusqlite_connection: &RusqliteConnection,
+    period: &mut RatingPeriod,
+    event: &EventData,
+    reimport: bool,
+) -> Result<(), Error> {
+    if reimport {
+        println!("Removing the previous import of {}...", event.event_name);
+        rusqlite_connection.delete_event(event.event_id)?;
+    }
+
+    // Every tag and profile is recorded as seen at the time of the event's
+    // last set. Players merged into another are recorded as the player they
+    // were merged into.
+    let seen_at = event
+        .sets
+        .last()
+        .map(|set| completed_at(set.time))
+        .transpose()?
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339();
+    let mut players = HashMap::new();
+    for (entrant_id, entrant) in &event.players {
+        let mut participants = Vec::new();
+        for ((name, global_id), profile) in
+            entrant.participants.iter().zip(&entrant.profiles)
+        {
+            let global_id = rusqlite_connection.select_merged_id(*global_id)?;
+            rusqlite_connection.record_alias(global_id, name, &seen_at)?;
+            rusqlite_connection.record_profile(global_id, profile, &seen_at)?;
+            participants.push((name.to_owned(), global_id));
+        }
+        players.insert(*entrant_id, (&entrant.name, participants));
+    }
+
+    // Teams are saved first so their sets can refer to them. Each entrant
+    // is mapped to the name and global id their sets are recorded with,
+    // which for a team are those of the team.
+    let is_team = event.players.values().any(EntrantInfo::is_team);
+    let mut entrants = HashMap::new();
+    for (entrant_id, (name, participants)) in &players {
+        let global_id = match is_team {
+            false => participants[0].1,
+            true => {
+                let team_id =
+                    rusqlite_connection.select_team(name, participants)?;
+                rusqlite_connection.insert_event_team(
+                    event.event_id,
+                    *entrant_id,
+                    team_id,
+                )?;
+                team_id
+            }
+        };
+        entrants.insert(*entrant_id, (name.to_string(), global_id));
+    }
+    let player = |id: &i32| {
+        entrants
+            .get(id)
+            .ok_or(Error::MissingField("entrant of a set in the list of entrants"))
+    };
+
+    // Create the tables for the rankings if needed.
+    let mut event_rating = EventRating::new(
+        rusqlite_connection,
+        period,
+        &event.game_name,
+        is_team,
+    )?;
+
+    // p1 tourney id, p1 score, p2 tourney id, p2 score, time. Only entrants
+    // who appear in a set are counted as having entered, the same as when
+    // the event is replayed from the sets table.
+    let mut last_set = None;
+    let mut played = Vec::new();
+    for set in event.sets.iter() {
+        let (player_one_name, player_one_global_id) = player(&set.player_one_id)?;
+        let (player_two_name, player_two_global_id) = player(&set.player_two_id)?;
+        let (player_one_global_id, player_two_global_id) =
+            (*player_one_global_id, *player_two_global_id);
+        for global_id in [player_one_global_id, player_two_global_id] {
+            if !played.contains(&global_id) {
+                played.push(global_id);
+            }
+        }
+        let dt = completed_at(set.time)?;
+
+        let mut set_struct = SetsRow {
+            player_one_global_id,
+            player_one_name: player_one_name.to_string(),
+            player_one_score: set.player_one_score,
+            player_two_global_id,
+            player_two_name: player_two_name.to_string(),
+            player_two_score: set.player_two_score,
+            tournament_name: event.event_name.clone(),
+            game_name: event.game_name.clone(),
+            set_time: dt.to_rfc3339(),
+            event_id: Some(event.event_id),
+            is_team,
+            ..SetsRow::default()
+        };
+
+        // Detect DQ first. If detected, all we do is record it in the
+        // set history.
+        if !is_dq(&set_struct) {
+            let (game_player_one, game_player_two) = event_rating
+                .rate_set(rusqlite_connection, &mut set_struct)
+                .context(|| {
+                    format!(
+                        "rating the set between {} and {} completed at {}",
+                        player_one_name, player_two_name, set_struct.set_time
+                    )
+                })?;
+
+            println!(
+                "P1: {} - Rating: {:?}, P2: {} - Rating: {:?}",
+                player_one_name,
+                event_rating.game_rating(&game_player_one),
+                player_two_name,
+                event_rating.game_rating(&game_player_two)
+            );
+        }
+
+        // Record the set.
+        set_struct.id = rusqlite_connection.insert_set(&set_struct)?;
+        if !is_dq(&set_struct) {
+            period.record_set(&set_struct);
+        }
+        last_set = Some(set_struct);
+    }
+
+    // Now that every set has been recorded, update the rankings and
+    // increment the relevant counters. Systems that wait for the rating
+    // period to end are ranked again once the whole tournament is rated.
+    let tables = event_rating.tables();
+    event_rating.finish(
+        rusqlite_connection,
+        period,
+        &played,
+        event_winner(last_set.as_ref()),
+    )?;
+    update_rankings(rusqlite_connection, &period.rating_kinds, &tables)?;
+
+    // Players are named after the tag they were seen under most recently,
+    // which isn't the tag of this event if it was an older one.
+    for table in player_tables(rusqlite_connection)? {
+        rusqlite_connection.refresh_names(&table)?;
+    }
+
+    rusqlite_connection.insert_event(&EventsRow {
+        event_id: event.event_id,
+        tournament_slug: event.tournament_slug.clone(),
+        event_name: event.event_name.clone(),
+        game_name: event.game_name.clone(),
+        game_id: Some(event.game_id),
+        imported_at: Utc::now().to_rfc3339(),
+        set_count: event.sets.len() as i32,
+    })?;
+
+    // The event was applied on top of ratings that had already counted any
+    // later events. Replay everything so it takes its place in the timeline
+    // and nothing from the previous import remains. The replay rates this
+    // tournament in full, so its rating period is forgotten rather than
+    // ended a second time.
+    if reimport {
+        println!("Replaying all events after re-import...");
+        recompute::replay_all(rusqlite_connection, &period.rating_kinds)?;
+        period.discard();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_slugs_and_urls_of_tournaments_and_events() {
+        let cases = [
+            ("genesis-9", "genesis-9", None),
+            ("  genesis-9/ ", "genesis-9", None),
+            ("tournament/genesis-9", "genesis-9", None),
+            ("https://smash.gg/tournament/genesis-9", "genesis-9", None),
+            ("https://start.gg/tournament/genesis-9/details", "genesis-9", None),
+            ("http://www.start.gg/tournament/genesis-9/", "genesis-9", None),
+            (
+                "https://start.gg/tournament/genesis-9/event/melee-singles",
+                "genesis-9",
+                Some("melee-singles"),
+            ),
+            (
+                "https://smash.gg/tournament/genesis-9/event/melee-singles/overview",
+                "genesis-9",
+                Some("melee-singles"),
+            ),
+            (
+                "https://start.gg/tournament/genesis-9/events/melee-singles/standings",
+                "genesis-9",
+                Some("melee-singles"),
+            ),
+            (
+                "https://start.gg/tournament/genesis-9/event/melee-singles/overview?tab=bracket",
+                "genesis-9",
+                Some("melee-singles"),
+            ),
+            (
+                "https://start.gg/tournament/genesis-9/event/melee-singles#standings",
+                "genesis-9",
+                Some("melee-singles"),
+            ),
+            ("https://start.gg/tournament/genesis-9?ref=home", "genesis-9", None),
+            ("https://start.gg/tournament/genesis-9#top", "genesis-9", None),
+        ];
+
+        for (input, slug, event_slug) in cases {
+            assert_eq!(
+                parse_tournament(input),
+                (slug.to_owned(), event_slug.map(str::to_owned)),
+                "parsing {:?}",
+                input
+            );
+        }
+    }
+}
@@ -1,151 +1,463 @@
-use crate::reqwest_wrapper::{Content, ContentType, ReqwestClient};
+use crate::error::{ApiError, ApiErrorKind, Error};
 use serde::Deserialize;
-use smashgg_elo::get_input;
+use sha2::{Digest, Sha256};
+use smashgg_elo::{clean_string, prompt_input};
 use std::collections::HashMap;
 
 const EVNT_PROMPT: &str = "Enter the id of one of the events to parse: ";
 
+/// Maps each entrant's tournament id to who they are.
+pub type EntrantMap = HashMap<i32, EntrantInfo>;
+
+/// An entrant of an event. Singles entrants have one participant, while
+/// teams in doubles and other team events have several.
+#[derive(Debug, Clone)]
+pub struct EntrantInfo {
+    /// The entrant's name, which for a team is usually its members' tags.
+    pub name: String,
+    /// The tag and global id of every participant.
+    pub participants: Vec<(String, i32)>,
+    /// The profile of every participant, in the same order.
+    pub profiles: Vec<PlayerProfile>,
+}
+
+/// What an event says about a participant besides their tag. Everything but
+/// the prefix comes from their account, so guests only ever have a prefix,
+/// and social handles are only known if the player made them public.
+#[derive(Debug, Clone, Default)]
+pub struct PlayerProfile {
+    /// The sponsor or team prefix the player entered the event under.
+    pub prefix: Option<String>,
+    /// The slug of the player's profile, as in start.gg/[slug].
+    pub slug: Option<String>,
+    pub country: Option<String>,
+    pub state: Option<String>,
+    pub city: Option<String>,
+    pub twitter: Option<String>,
+    pub discord: Option<String>,
+}
+
+impl EntrantInfo {
+    /// Returns true if the entrant is a team of several players.
+    pub fn is_team(&self) -> bool {
+        self.participants.len() > 1
+    }
+}
+
+/// The global id given to a participant who has no smash.gg account, such as
+/// a guest registered on site. It is derived from their tag, ignoring case
+/// and punctuation, so the same guest gets the same id at every event. Guest
+/// ids are negative so they never clash with the id of an account.
+pub fn guest_id(gamer_tag: &str) -> i32 {
+    let tag = match clean_string(gamer_tag) {
+        tag if tag.is_empty() => gamer_tag.trim().to_lowercase(),
+        tag => tag,
+    };
+    let digest = Sha256::new().chain_update("guest:").chain_update(tag).finalize();
+    let hash = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+    -((hash & 0x3fff_ffff) as i32) - 1
+}
+
+/// Returns true if a global id was made up for a guest rather than being
+/// the id of a smash.gg account.
+pub fn is_guest(global_id: i32) -> bool {
+    global_id < 0
+}
+
+/// Which events of a tournament to ingest.
+pub enum EventSelection {
+    /// The event with the given id.
+    Id(i32),
+    /// The event with the given slug, as found in the URL of the event.
+    Slug(String),
+    /// Every event whose game matches one of the given names or ids, or
+    /// every event if none are given.
+    Games(Vec<String>),
+    /// Ask the user to pick one event.
+    Prompt,
+}
+
+/// An event of a tournament and the game it was played in.
+#[derive(Debug, Clone)]
+pub struct EventInfo {
+    pub id: i32,
+    pub name: String,
+    /// The slug of the event within its tournament, as in
+    /// start.gg/tournament/[tournament slug]/event/[event slug].
+    pub slug: Option<String>,
+    pub game_id: i32,
+    pub game_name: String,
+    /// When the event starts as a unix timestamp, or the tournament if the
+    /// event has no start time of its own.
+    pub start_at: Option<i64>,
+    pub is_online: Option<bool>,
+    /// The state of the event, such as CREATED, ACTIVE or COMPLETED.
+    pub state: Option<String>,
+}
+
+/// A tournament found by a discovery search, with its events that matched.
+#[derive(Debug)]
+pub struct DiscoveredTournament {
+    pub slug: String,
+    pub name: String,
+    pub start_at: Option<i64>,
+    /// The city, state and country the tournament is held in, as far as
+    /// they are known.
+    pub location: String,
+    pub events: Vec<EventInfo>,
+}
+
+impl EventInfo {
+    /// Returns true if the event has finished, so its results won't change.
+    pub fn is_completed(&self) -> bool {
+        self.state.as_deref() == Some("COMPLETED")
+    }
+
+    /// Returns true if the event is for the game with the given name or id.
+    pub fn is_game(&self, game: &str) -> bool {
+        game.trim().eq_ignore_ascii_case(&self.game_name)
+            || game.trim().parse() == Ok(self.game_id)
+    }
+}
+
 /// Generic entry point for all post requests. The top level struct acts as
 /// the object that the rest of the program interfaces with to get the data.
 #[derive(Deserialize, Debug)]
 pub struct PostResponse {
-    data: Data,
+    data: Option<Data>,
+    errors: Option<Vec<GraphQlError>>,
+    // Requests rejected before reaching GraphQL, such as for a bad token or
+    // the rate limit, get a message in place of data and errors.
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GraphQlError {
+    message: String,
 }
 
 impl PostResponse {
+    /// Turns a response into an error if smash.gg reported one, through
+    /// either the HTTP status or the body of the response.
+    pub fn check(self, status: u16) -> Result<Self, Error> {
+        let mut messages: Vec<String> = self
+            .errors
+            .iter()
+            .flatten()
+            .map(|error| error.message.to_owned())
+            .collect();
+        messages.extend(self.message.iter().cloned());
+
+        match (200..300).contains(&status) && messages.is_empty() {
+            true => Ok(self),
+            false => Err(Error::Response(ApiError::new(status, messages))),
+        }
+    }
+
+    fn data(self) -> Result<Data, Error> {
+        self.data.ok_or(Error::MissingField("data"))
+    }
+
     /// Once the initial post request has been made, this function takes the
-    /// JSON response and parses it for all events in a tournament. The user
-    /// will then be continually asked for which event they wish to parse.
-    pub fn get_event_info(self) -> (i32, String, String) {
-        let tournament = self.data.tournament();
-        let num_evnts: i32 = (tournament.events.len() - 1).try_into().unwrap();
+    /// JSON response and parses it for the events in a tournament to ingest.
+    /// Events can be selected up front by id or by game. Otherwise the user
+    /// will be continually asked for which event they wish to parse.
+    pub fn get_events(
+        self,
+        selection: &EventSelection,
+    ) -> Result<Vec<EventInfo>, Error> {
+        let events = self.data()?.tournament()?.event_infos();
+        if events.is_empty() {
+            return Err(Error::MissingField("events in the tournament"));
+        }
+        let num_evnts = events.len() as i32 - 1;
+
+        match selection {
+            EventSelection::Id(event_id) => {
+                return events
+                    .into_iter()
+                    .find(|event| event.id == *event_id)
+                    .map(|event| vec![event])
+                    .ok_or_else(|| {
+                        Error::InvalidInput(format!(
+                            "No event with id {} in the tournament",
+                            event_id
+                        ))
+                    });
+            }
+            EventSelection::Slug(slug) => {
+                return events
+                    .into_iter()
+                    .find(|event| event.slug.as_deref() == Some(slug.as_str()))
+                    .map(|event| vec![event])
+                    .ok_or_else(|| {
+                        Error::InvalidInput(format!(
+                            "No event {:?} in the tournament",
+                            slug
+                        ))
+                    });
+            }
+            EventSelection::Games(games) => {
+                let matching: Vec<EventInfo> = events
+                    .into_iter()
+                    .filter(|event| {
+                        games.is_empty()
+                            || games.iter().any(|game| event.is_game(game))
+                    })
+                    .collect();
+                return match matching.is_empty() {
+                    true => Err(Error::InvalidInput(format!(
+                        "No events of the tournament are for {}",
+                        games.join(", ")
+                    ))),
+                    false => Ok(matching),
+                };
+            }
+            EventSelection::Prompt => (),
+        }
 
         // Print all events to the console with the associated game and event
         // name. Loop continuously until the user selects one to parse.
         loop {
             println!("List of events found in the tournament:");
-            for (count, event) in tournament.events.iter().enumerate() {
-                println!("{}: {:?} - {:?}", count, event.videogame.name, event.name);
+            for (count, event) in events.iter().enumerate() {
+                println!("{}: {:?} - {:?}", count, event.game_name, event.name);
             }
 
-            let event_input: i32 = get_input(EVNT_PROMPT);
+            let event_input: i32 = prompt_input(EVNT_PROMPT)
+                .ok_or(Error::MissingInput("event id"))?;
             match event_input {
                 i if i < 0 => continue,
                 i if i > num_evnts => continue,
                 _ => {
-                    let info = &tournament.events[event_input as usize];
-                    return (
-                        info.id,
-                        info.videogame.name.to_owned(),
-                        info.name.to_owned(),
-                    );
+                    let mut events = events;
+                    return Ok(vec![events.swap_remove(event_input as usize)]);
                 }
             };
         }
     }
 
-    pub fn get_total_pages(self) -> i32 {
-        self.data.event().sets().page_info().total_pages
+    /// Parses a page of tournaments found by a discovery search, along with
+    /// the total number of pages found.
+    pub fn get_tournaments(
+        self,
+    ) -> Result<(Vec<DiscoveredTournament>, i32), Error> {
+        let tournaments = self
+            .data()?
+            .tournaments
+            .ok_or(Error::MissingField("tournaments"))?;
+        let total_pages = tournaments
+            .page_info
+            .ok_or(Error::MissingField("page info in tournaments"))?
+            .total_pages()?;
+
+        let discovered = tournaments
+            .nodes
+            .ok_or(Error::MissingField("nodes in tournaments"))?
+            .into_iter()
+            .map(|tournament| {
+                let slug = tournament.slug.clone().unwrap_or_default();
+                let location = [
+                    &tournament.city,
+                    &tournament.addr_state,
+                    &tournament.country_code,
+                ]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ");
+
+                DiscoveredTournament {
+                    // Slugs come back as tournament/[tournament slug].
+                    slug: slug.trim_start_matches("tournament/").to_owned(),
+                    name: tournament.name.clone().unwrap_or_default(),
+                    start_at: tournament.start_at,
+                    location,
+                    events: tournament.event_infos(),
+                }
+            })
+            .collect();
+
+        Ok((discovered, total_pages))
+    }
+
+    /// Gets the number of pages of the entrants or sets of an event that
+    /// this response is a page of, counted from the total number of them
+    /// and the number on each page.
+    pub fn page_count(&self, per_page: i32) -> Result<i32, Error> {
+        let event = self
+            .data
+            .as_ref()
+            .and_then(|data| data.event.as_ref())
+            .ok_or_else(|| not_found("No event matches the id"))?;
+        let page_info = match (&event.entrants, &event.sets) {
+            (Some(entrants), _) => entrants.page_info.as_ref(),
+            (_, Some(sets)) => sets.page_info.as_ref(),
+            _ => None,
+        };
+
+        page_info
+            .ok_or(Error::MissingField("page info of entrants or sets"))?
+            .page_count(per_page)
     }
 
     /// Gets all sets in an event. Iterates through the all of the sets and
     /// their results in an event and records them in a vector that will be
     /// later parsed.
-    pub fn get_sets_info(self) -> Vec<SetInfo> {
+    pub fn get_sets_info(self) -> Result<Vec<SetInfo>, Error> {
         let mut set_vec = Vec::new();
 
-        let player_nodes = self.data.event().sets().nodes();
+        let player_nodes = self.data()?.event()?.sets()?.nodes()?;
         for node in player_nodes {
-            let player_one = &node.slots()[0];
-            let player_two = &node.slots()[1];
+            let (player_one, player_two) = match node.slots()?.as_slice() {
+                [player_one, player_two] => (player_one, player_two),
+                _ => return Err(Error::MissingField("two slots in a set")),
+            };
 
             set_vec.push(SetInfo {
-                player_one_id: player_one.entrant().id(),
-                player_one_score: player_one.standing().stats.score.value(),
-                player_two_id: player_two.entrant().id(),
-                player_two_score: player_two.standing().stats.score.value(),
-                time: node.completed_at(),
+                player_one_id: player_one.entrant()?.id()?,
+                player_one_score: player_one.standing()?.stats.score.value(),
+                player_two_id: player_two.entrant()?.id()?,
+                player_two_score: player_two.standing()?.stats.score.value(),
+                time: node.completed_at()?,
             });
         }
         set_vec.reverse();
-        set_vec
+        Ok(set_vec)
     }
 
-    /// Repeatedly queries smash.gg's api and collects all of the players
-    /// that participated in an event. Maps each player's tournament id to
-    /// their global smash.gg id and name.
-    pub fn construct_players(
-        self,
-        reqwest_client: &mut ReqwestClient,
-        event_id: i32,
-    ) -> HashMap<i32, (String, i32)> {
-        // Detect how many calls to the api that we need to make to record
-        // all players in an event.
-        let mut player_map = HashMap::new();
-        let page_info = self.data.event().entrants().page_info();
-        println!("Constructing the list of players...");
-
-        // Call multiple times and record each player that participated.
-        println!("Found {} pages of player data", page_info.total_pages);
-        for i in 1..page_info.total_pages + 1 {
-            println!("Processing page {} out of {}...", i, page_info.total_pages);
-            let mut content = Content::new();
-            content.variables.event_id = Some(event_id);
-            content.variables.page = Some(i);
-            content.edit_content(ContentType::Page);
-            reqwest_client.construct_json(&content);
-
-            let json: PostResponse = match reqwest_client.send_post().json() {
-                Ok(json) => json,
-                Err(err) => panic!("Error in converting to json {}", err),
+    /// Gets the entrants on a page of the entrants of an event. Maps each
+    /// entrant's tournament id to their name and the name and global
+    /// smash.gg id of every participant.
+    pub fn get_entrants(self) -> Result<EntrantMap, Error> {
+        let mut entrant_map = HashMap::new();
+
+        let nodes = self.data()?.event()?.entrants()?.nodes()?;
+        for entrant in nodes {
+            let participants: Vec<(String, i32)> = entrant
+                .participants()?
+                .iter()
+                .map(|participant| {
+                    (participant.gamer_tag.to_owned(), participant.global_id())
+                })
+                .collect();
+            let profiles = entrant
+                .participants()?
+                .iter()
+                .map(Participants::profile)
+                .collect();
+            let name = match (&entrant.name, participants.as_slice()) {
+                (_, []) => {
+                    return Err(Error::MissingField("participants of an entrant"))
+                }
+                (_, [(gamer_tag, _)]) => gamer_tag.to_owned(),
+                (Some(name), _) => name.to_owned(),
+                (None, _) => participants
+                    .iter()
+                    .map(|(gamer_tag, _)| gamer_tag.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" / "),
             };
 
-            let nodes = json.data.event().entrants().nodes();
-
-            for player in nodes {
-                player_map.insert(
-                    player.id(),
-                    (
-                        player.participants()[0].gamer_tag.to_owned(),
-                        player.participants()[0].user.id(),
-                    ),
-                );
-            }
+            entrant_map.insert(
+                entrant.id()?,
+                EntrantInfo {
+                    name,
+                    participants,
+                    profiles,
+                },
+            );
         }
 
-        player_map
+        Ok(entrant_map)
     }
 }
 #[derive(Deserialize, Debug)]
 struct Data {
     tournament: Option<Tournament>,
+    tournaments: Option<Tournaments>,
     event: Option<Event>,
 }
 
 impl Data {
-    fn tournament(&self) -> &Tournament {
-        self.tournament.as_ref().expect("Matching error: No tournament found")
-
+    // smash.gg answers with null rather than an error when nothing matches
+    // the slug or id that was asked for.
+    fn tournament(self) -> Result<Tournament, Error> {
+        self.tournament
+            .ok_or_else(|| not_found("No tournament matches the slug"))
     }
 
-    fn event(self) -> Event {
-        self.event.expect("Matching error: No event found")
+    fn event(self) -> Result<Event, Error> {
+        self.event.ok_or_else(|| not_found("No event matches the id"))
     }
 }
 
+fn not_found(message: &str) -> Error {
+    Error::Response(ApiError {
+        status: 200,
+        kind: ApiErrorKind::NotFound,
+        messages: vec![message.to_string()],
+    })
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Tournaments {
+    page_info: Option<PageInfo>,
+    nodes: Option<Vec<Tournament>>,
+}
+
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct Tournament {
-    events: Vec<Events>,
+    name: Option<String>,
+    slug: Option<String>,
+    start_at: Option<i64>,
+    city: Option<String>,
+    addr_state: Option<String>,
+    country_code: Option<String>,
+    events: Option<Vec<Events>>,
+}
+
+impl Tournament {
+    fn event_infos(&self) -> Vec<EventInfo> {
+        self.events
+            .iter()
+            .flatten()
+            .map(|event| EventInfo {
+                id: event.id,
+                name: event.name.to_owned(),
+                // Slugs come back as tournament/[slug]/event/[event slug].
+                slug: event.slug.as_ref().and_then(|slug| {
+                    slug.rsplit('/').next().map(str::to_owned)
+                }),
+                game_id: event.videogame.id,
+                game_name: event.videogame.name.to_owned(),
+                start_at: event.start_at.or(self.start_at),
+                is_online: event.is_online,
+                state: event.state.to_owned(),
+            })
+            .collect()
+    }
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct Events {
     id: i32,
     name: String,
+    slug: Option<String>,
+    start_at: Option<i64>,
+    is_online: Option<bool>,
+    state: Option<String>,
     videogame: Videogame,
 }
 #[derive(Deserialize, Debug)]
 struct Videogame {
+    id: i32,
     name: String,
 }
 #[derive(Deserialize, Debug)]
@@ -155,12 +467,12 @@ struct Event {
 }
 
 impl Event {
-    fn entrants(self) -> Entrants {
-        self.entrants.expect("Matching error: No entrants found")
+    fn entrants(self) -> Result<Entrants, Error> {
+        self.entrants.ok_or(Error::MissingField("entrants"))
     }
 
-    fn sets(self) -> Sets {
-        self.sets.expect("Matching error: No sets found")
+    fn sets(self) -> Result<Sets, Error> {
+        self.sets.ok_or(Error::MissingField("sets"))
     }
 }
 
@@ -172,12 +484,8 @@ struct Entrants {
 }
 
 impl Entrants {
-    fn page_info(self) -> PageInfo {
-        self.page_info.expect("Matching error: No page info found in entrants")
-    }
-
-    fn nodes(self) -> Vec<Nodes> {
-        self.nodes.expect("Matching error: No nodes found in entrants")
+    fn nodes(self) -> Result<Vec<Nodes>, Error> {
+        self.nodes.ok_or(Error::MissingField("nodes in entrants"))
     }
 }
 
@@ -189,46 +497,59 @@ struct Sets {
 }
 
 impl Sets {
-    fn page_info(self) -> PageInfo {
-        self.page_info.expect("Matching error: No page info found in sets")
-    }
-
-    fn nodes(self) -> Vec<Nodes> {
-        self.nodes.expect("Matching error: No nodes found in sets")
+    fn nodes(self) -> Result<Vec<Nodes>, Error> {
+        self.nodes.ok_or(Error::MissingField("nodes in sets"))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct PageInfo {
-    total_pages: i32,
+    total: Option<i32>,
+    total_pages: Option<i32>,
+}
+
+impl PageInfo {
+    fn total_pages(&self) -> Result<i32, Error> {
+        self.total_pages
+            .ok_or(Error::MissingField("total pages in page info"))
+    }
+
+    // The number of pages needed to hold the total at the given page size.
+    // There is always at least the first page.
+    fn page_count(&self, per_page: i32) -> Result<i32, Error> {
+        let total = self.total.ok_or(Error::MissingField("total in page info"))?;
+        let per_page = per_page.max(1);
+        Ok(((total + per_page - 1) / per_page).max(1))
+    }
 }
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct Nodes {
     id: Option<i32>,
+    name: Option<String>,
     participants: Option<Vec<Participants>>,
     completed_at: Option<i64>,
     slots: Option<Vec<Slots>>,
 }
 
 impl Nodes {
-    fn id(&self) -> i32 {
-        self.id.expect("Matching error: No id found")
+    fn id(&self) -> Result<i32, Error> {
+        self.id.ok_or(Error::MissingField("id"))
     }
 
-    fn participants(&self) -> &Vec<Participants> {
+    fn participants(&self) -> Result<&Vec<Participants>, Error> {
         self.participants
             .as_ref()
-            .expect("Matching error: No participants found")
+            .ok_or(Error::MissingField("participants"))
     }
 
-    fn completed_at(&self) -> i64 {
-        self.completed_at.expect("Matching error: No time found")
+    fn completed_at(&self) -> Result<i64, Error> {
+        self.completed_at.ok_or(Error::MissingField("completion time of a set"))
     }
 
-    fn slots(&self) -> &Vec<Slots> {
-        self.slots.as_ref().expect("Matching error: No slots found")
+    fn slots(&self) -> Result<&Vec<Slots>, Error> {
+        self.slots.as_ref().ok_or(Error::MissingField("slots"))
     }
 }
 
@@ -236,19 +557,74 @@ impl Nodes {
 #[serde(rename_all = "camelCase")]
 struct Participants {
     gamer_tag: String,
-    user: User,
+    prefix: Option<String>,
+    user: Option<User>,
+}
+
+impl Participants {
+    // The id of the participant's account, or an id made up from their tag
+    // if they don't have one.
+    fn global_id(&self) -> i32 {
+        match self.user.as_ref().and_then(|user| user.id) {
+            Some(global_id) => global_id,
+            None => guest_id(&self.gamer_tag),
+        }
+    }
+
+    fn profile(&self) -> PlayerProfile {
+        let user = self.user.as_ref();
+        let location = user.and_then(|user| user.location.as_ref());
+        // smash.gg sends empty strings for fields that were left blank.
+        let field = |value: Option<&String>| {
+            value.filter(|value| !value.trim().is_empty()).cloned()
+        };
+
+        PlayerProfile {
+            prefix: field(self.prefix.as_ref()),
+            slug: field(user.and_then(|user| user.slug.as_ref())),
+            country: field(location.and_then(|location| location.country.as_ref())),
+            state: field(location.and_then(|location| location.state.as_ref())),
+            city: field(location.and_then(|location| location.city.as_ref())),
+            twitter: field(user.and_then(|user| user.handle("TWITTER"))),
+            discord: field(user.and_then(|user| user.handle("DISCORD"))),
+        }
+    }
 }
 #[derive(Deserialize, Debug)]
 struct User {
     id: Option<i32>,
+    slug: Option<String>,
+    location: Option<Location>,
+    authorizations: Option<Vec<Authorization>>,
 }
 
 impl User {
-    fn id(&self) -> i32 {
-        self.id.expect("Matching error in user: No id found")
+    // The player's username on a connected account of the given type, such
+    // as TWITTER or DISCORD.
+    fn handle(&self, kind: &str) -> Option<&String> {
+        self.authorizations
+            .iter()
+            .flatten()
+            .find(|authorization| authorization.kind.as_deref() == Some(kind))
+            .and_then(|authorization| authorization.external_username.as_ref())
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct Location {
+    country: Option<String>,
+    state: Option<String>,
+    city: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Authorization {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    external_username: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 struct Slots {
     entrant: Option<Entrant>,
@@ -256,12 +632,12 @@ struct Slots {
 }
 
 impl Slots {
-    fn entrant(&self) -> &Entrant {
-        self.entrant.as_ref().expect("Matching error: No entrant found")
+    fn entrant(&self) -> Result<&Entrant, Error> {
+        self.entrant.as_ref().ok_or(Error::MissingField("entrant"))
     }
 
-    fn standing(&self) -> &Standing {
-        self.standing.as_ref().expect("Matching error: No standing found")
+    fn standing(&self) -> Result<&Standing, Error> {
+        self.standing.as_ref().ok_or(Error::MissingField("standing"))
     }
 }
 #[derive(Deserialize, Debug)]
@@ -270,8 +646,8 @@ struct Entrant {
 }
 
 impl Entrant {
-    fn id(&self) -> i32 {
-        self.id.expect("Matching error in entrant: No id found")
+    fn id(&self) -> Result<i32, Error> {
+        self.id.ok_or(Error::MissingField("id of an entrant"))
     }
 }
 
@@ -292,10 +668,7 @@ struct Score {
 // is none for a set. We thus then treat it as iff they have been DQ'd.
 impl Score {
     fn value(&self) -> i32 {
-        match self.value {
-            Some(value) => value,
-            None => -1,
-        }
+        self.value.unwrap_or(-1)
     }
 }
 
@@ -307,3 +680,24 @@ pub struct SetInfo {
     pub player_two_score: i32,
     pub time: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_pages_from_the_total() {
+        let page_info = |total| PageInfo {
+            total: Some(total),
+            total_pages: None,
+        };
+
+        assert_eq!(page_info(0).page_count(70).unwrap(), 1);
+        assert_eq!(page_info(70).page_count(70).unwrap(), 1);
+        assert_eq!(page_info(71).page_count(70).unwrap(), 2);
+        assert_eq!(page_info(499).page_count(35).unwrap(), 15);
+        assert!(PageInfo { total: None, total_pages: Some(3) }
+            .page_count(70)
+            .is_err());
+    }
+}
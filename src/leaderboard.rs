@@ -0,0 +1,120 @@
+use crate::cli::{LeaderboardArgs, RatingArgs};
+use crate::config::{Config, Region};
+use crate::error::{Context, Error};
+use crate::ingest::{self, PLAYERS};
+use crate::json::PlayerProfile;
+use crate::rating;
+use crate::rusqlite_wrapper::{PlayersRow, RusqliteConnection};
+use smashgg_elo::clean_string;
+use std::collections::HashMap;
+
+/// Ranks the players of one or every region in the config file. Players
+/// keep the ratings they earned everywhere, and are ranked by them among
+/// the other players of the region. Each region's ranks are saved in the
+/// regional_ranks table and its best players are printed.
+pub fn leaderboard(
+    db_path: &str,
+    config: &Config,
+    ratings: RatingArgs,
+    args: LeaderboardArgs,
+) -> Result<(), Error> {
+    let regions = select_regions(config, args.region.as_deref())?;
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+
+    // Games are chosen by name or id as when ingesting, or by the name of
+    // their table.
+    let table = match &args.game {
+        Some(game) => match rusqlite_connection.select_game_name(game)? {
+            Some(game_name) => clean_string(&game_name),
+            None => clean_string(game),
+        },
+        None => PLAYERS.to_owned(),
+    };
+    if !ingest::player_tables(&rusqlite_connection)?.contains(&table) {
+        return Err(Error::InvalidInput(format!(
+            "No players have been rated in {}",
+            args.game.as_deref().unwrap_or(PLAYERS)
+        )));
+    }
+
+    // Players are ranked by the first rating system, as in the rating
+    // tables themselves.
+    let rating_kinds = ingest::resolve_rating_kinds(&rusqlite_connection, ratings)?;
+    let rating_system = rating::build_systems(&rating_kinds).remove(0);
+    let mut players = rusqlite_connection.select_players(&table)?;
+    players.sort_by(|a, b| {
+        rating_system
+            .rating(b)
+            .total_cmp(&rating_system.rating(a))
+    });
+    let locations = rusqlite_connection.select_locations()?;
+
+    rusqlite_connection.transaction(|| {
+        for (name, region) in &regions {
+            let ranked = rank_region(region, &players, &locations);
+            let global_ids: Vec<i32> =
+                ranked.iter().map(|player| player.global_id).collect();
+            rusqlite_connection.replace_regional_ranks(name, &table, &global_ids)?;
+
+            println!("{} - {} players", name, ranked.len());
+            for (rank, player) in ranked.iter().take(args.top).enumerate() {
+                println!(
+                    "{}: {} - Rating: {}",
+                    rank + 1,
+                    player.name,
+                    rating_system.rating(player)
+                );
+            }
+        }
+        Ok::<(), Error>(())
+    })?;
+
+    println!("Finished ranking!");
+    Ok(())
+}
+
+// The regions to rank, sorted by name: the one that was asked for, or every
+// region in the config file.
+fn select_regions<'a>(
+    config: &'a Config,
+    region: Option<&str>,
+) -> Result<Vec<(&'a String, &'a Region)>, Error> {
+    let mut regions: Vec<(&String, &Region)> = config
+        .regions
+        .iter()
+        .filter(|(name, _)| region.is_none_or(|region| region == name.as_str()))
+        .collect();
+    regions.sort_unstable_by_key(|(name, _)| *name);
+
+    match (regions.is_empty(), region) {
+        (false, _) => Ok(regions),
+        (true, Some(region)) => Err(Error::InvalidInput(format!(
+            "No region named {:?} in the config file",
+            region
+        ))),
+        (true, None) => Err(Error::InvalidInput(
+            "No regions are defined under [regions] in the config file".to_string(),
+        )),
+    }
+}
+
+// The players of a region, keeping the order they were given in.
+fn rank_region<'a>(
+    region: &Region,
+    players: &'a [PlayersRow],
+    locations: &HashMap<i32, PlayerProfile>,
+) -> Vec<&'a PlayersRow> {
+    players
+        .iter()
+        .filter(|player| {
+            let location = locations.get(&player.global_id);
+            region.contains(
+                player.global_id,
+                location.and_then(|location| location.country.as_deref()),
+                location.and_then(|location| location.state.as_deref()),
+                location.and_then(|location| location.city.as_deref()),
+            )
+        })
+        .collect()
+}
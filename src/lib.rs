@@ -1,17 +1,21 @@
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
 /// Generic function that takes in a prompt and converts the string to a type.
-pub fn get_input<U: std::str::FromStr>(prompt: &str) -> U {
+/// Returns None if stdin is closed or can't be read.
+pub fn get_input<U: std::str::FromStr>(prompt: &str) -> Option<U> {
     loop {
         let mut input = String::new();
 
         // Print prompt to the screen and flush output.
         print!("{}", prompt);
-        let _ = io::stdout().flush().expect("Failed to flush stdout.");
+        io::stdout().flush().ok()?;
 
-        // Read in the string from stdin.
-        io::stdin().read_line(&mut input).expect("Failed to read input.");
+        // Read in the string from stdin. Nothing read means stdin closed.
+        match io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => (),
+        }
 
         // Convert to specified type.
         // If successful, bind it to the variable input
@@ -21,7 +25,17 @@ pub fn get_input<U: std::str::FromStr>(prompt: &str) -> U {
             Err(_) => continue,
         };
 
-        return input;
+        return Some(input);
+    }
+}
+
+/// Prompts the user for input only when a terminal is attached to stdin.
+/// Returns None instead of blocking when running non-interactively, such as
+/// from cron or a pipe.
+pub fn prompt_input<U: std::str::FromStr>(prompt: &str) -> Option<U> {
+    match io::stdin().is_terminal() {
+        true => get_input(prompt),
+        false => None,
     }
 }
 
@@ -35,5 +49,5 @@ pub fn clean_string(string: &str) -> String {
         }
     }
 
-    return clean_string.iter().collect::<String>();
+    clean_string.iter().collect::<String>()
 }
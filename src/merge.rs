@@ -0,0 +1,104 @@
+use crate::cli::{MergeArgs, RatingArgs};
+use crate::error::{Context, Error};
+use crate::ingest;
+use crate::recompute;
+use crate::rusqlite_wrapper::RusqliteConnection;
+
+/// Merges one player into another, such as a duplicate account or a guest
+/// who has since made an account. Their sets, tags and teams are moved to
+/// the other player and every rating is recomputed, so their history counts
+/// as one player's. Sets imported later under the merged id go to the other
+/// player too.
+pub fn merge_players(
+    db_path: &str,
+    ratings: RatingArgs,
+    args: MergeArgs,
+) -> Result<(), Error> {
+    let from = args.from;
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+
+    // A player merged away earlier lives on as the player they were merged
+    // into, so that is who is merged into instead.
+    let into = rusqlite_connection.select_merged_id(args.into)?;
+    if into != args.into {
+        println!("Player {} was merged into {} before", args.into, into);
+    }
+    if from == into {
+        return Err(Error::InvalidInput(
+            "Can't merge a player into themselves".to_string(),
+        ));
+    }
+
+    // The merge and the replay happen in one transaction, so a failure
+    // leaves both players as they were.
+    rusqlite_connection.transaction(|| {
+        let rating_kinds =
+            ingest::resolve_rating_kinds(&rusqlite_connection, ratings)?;
+        // A mistyped id would otherwise become a new player that can't be
+        // unmerged, so both players must have been recorded.
+        for global_id in [from, into] {
+            if !rusqlite_connection.has_player(global_id)? {
+                return Err(Error::InvalidInput(format!(
+                    "No player with the id {} has been recorded",
+                    global_id
+                )));
+            }
+        }
+
+        println!("Merging player {} into {}...", from, into);
+        merge_teams(&rusqlite_connection, from, into)?;
+        rusqlite_connection.merge_player(from, into)?;
+
+        // Every rating table is rebuilt from the sets, which now only know
+        // the player and teams they were merged into.
+        recompute::replay_all(&rusqlite_connection, &rating_kinds)
+    })
+    .context(|| format!("merging player {} into {}", from, into))?;
+
+    println!("Finished merging!");
+    Ok(())
+}
+
+// Replaces the player with the other in every team they were part of. As a
+// team is its set of players, each becomes another team, possibly one that
+// has played before. Players who were teammates can't be merged, since
+// their team would be left with a single member.
+fn merge_teams(
+    rusqlite_connection: &RusqliteConnection,
+    from: i32,
+    into: i32,
+) -> Result<(), Error> {
+    let teams = rusqlite_connection.select_member_teams(from)?;
+    let mut team_members = Vec::new();
+    for (team_id, name) in teams {
+        let members = rusqlite_connection.select_team_members(team_id)?;
+        if members.iter().any(|(_, global_id)| *global_id == into) {
+            return Err(Error::InvalidInput(format!(
+                "Players {} and {} were teammates in {}, so they can't be \
+                the same player",
+                from, into, name
+            )));
+        }
+        team_members.push((team_id, name, members));
+    }
+
+    // The player is listed in their new teams under the tag they were seen
+    // under most recently.
+    let into_name = rusqlite_connection.select_latest_name(into)?;
+    for (team_id, name, members) in team_members {
+        let mut members: Vec<(String, i32)> = members
+            .into_iter()
+            .map(|(member_name, global_id)| match global_id == from {
+                true => (into_name.clone().unwrap_or(member_name), into),
+                false => (member_name, global_id),
+            })
+            .collect();
+        members.sort_unstable_by_key(|(_, global_id)| *global_id);
+
+        let merged_into = rusqlite_connection.select_team(&name, &members)?;
+        rusqlite_connection.merge_team(team_id, merged_into)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::cli::RatingKind;
+use crate::elo::Elo;
+use crate::glicko::Glicko2;
+use crate::PlayersRow;
+
+/// The result of a completed set as the number of games each player won.
+pub struct SetResult {
+    pub score_one: i32,
+    pub score_two: i32,
+}
+
+/// The new state of both players after a set, and how much their rating
+/// changed because of it.
+pub struct RatingChange {
+    pub player_one: PlayersRow,
+    pub player_two: PlayersRow,
+    pub delta_one: f64,
+    pub delta_two: f64,
+}
+
+/// A rating system that can be run over the sets of an event. Each system
+/// reads and writes its own rating columns of a player, so several can be
+/// run side by side over the same tables.
+pub trait RatingSystem {
+    /// Column of the player tables holding the rating players are ranked by.
+    fn rating_column(&self) -> &'static str;
+
+    /// The player's current rating in this system.
+    fn rating(&self, player: &PlayersRow) -> f64;
+
+    /// Rates a completed set between two players. Win/loss statistics are
+    /// shared by every system and are not updated here.
+    fn rate_set(
+        &mut self,
+        player_one: &PlayersRow,
+        player_two: &PlayersRow,
+        result: &SetResult,
+    ) -> RatingChange;
+
+    /// Whether ratings only change once a tournament is over, in which case
+    /// `end_rating_period` is given every player of the table to update.
+    fn has_rating_periods(&self) -> bool {
+        false
+    }
+
+    /// Ends the rating period covering the sets rated so far.
+    fn end_rating_period(&mut self, _players: &mut [PlayersRow]) {}
+}
+
+/// Creates a fresh instance of every configured rating system. Each player
+/// table needs its own instances as systems may keep state over a
+/// tournament.
+pub fn build_systems(kinds: &[RatingKind]) -> Vec<Box<dyn RatingSystem>> {
+    kinds
+        .iter()
+        .map(|kind| -> Box<dyn RatingSystem> {
+            match kind {
+                RatingKind::Elo => Box::new(Elo),
+                RatingKind::Glicko2 => Box::new(Glicko2::new()),
+            }
+        })
+        .collect()
+}
+
+/// Runs a set through every rating system in turn and then records the games
+/// in both players' statistics. The returned deltas are those of the first,
+/// or primary, rating system.
+pub fn rate_set(
+    systems: &mut [Box<dyn RatingSystem>],
+    mut player_one: PlayersRow,
+    mut player_two: PlayersRow,
+    result: &SetResult,
+) -> RatingChange {
+    let mut deltas = Vec::with_capacity(systems.len());
+
+    for system in systems.iter_mut() {
+        let change = system.rate_set(&player_one, &player_two, result);
+        player_one = change.player_one;
+        player_two = change.player_two;
+        deltas.push((change.delta_one, change.delta_two));
+    }
+
+    player_one.record_games(result.score_one, result.score_two);
+    player_two.record_games(result.score_two, result.score_one);
+
+    let (delta_one, delta_two) = deltas.first().copied().unwrap_or_default();
+    RatingChange {
+        player_one,
+        player_two,
+        delta_one,
+        delta_two,
+    }
+}
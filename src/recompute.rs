@@ -0,0 +1,197 @@
+use crate::cli::{RatingArgs, RatingKind};
+use crate::error::{Context, Error};
+use crate::ingest::{self, EventRating, RatingPeriod, PLAYERS};
+use crate::rusqlite_wrapper::{RusqliteConnection, SetsRow};
+use chrono::DateTime;
+use std::collections::HashMap;
+
+// Sets recorded before the event id was saved are grouped into events by
+// tournament and game name. A gap longer than this between two such sets
+// starts a new event, so recurring weeklies with the same name stay apart.
+const LEGACY_EVENT_GAP_SECS: i64 = 2 * 24 * 60 * 60;
+
+/// Wipes every rating and statistic derived from the sets table and replays
+/// every set in the order they were completed, one event at a time. The
+/// ratings recorded on each set are regenerated along with player stats,
+/// ranks, tournament counts and tournament wins.
+pub fn recompute(db_path: &str, ratings: RatingArgs) -> Result<(), Error> {
+    let rusqlite_connection = RusqliteConnection::new(db_path)
+        .context(|| format!("opening the database at {}", db_path))?;
+
+    // Everything is recalculated, so a different set of rating systems can
+    // be chosen here without mixing up ratings. The whole replay happens in
+    // one transaction so a failure leaves the old ratings untouched.
+    rusqlite_connection.transaction(|| {
+        if !ratings.rating_system.is_empty() {
+            ingest::save_rating_kinds(&rusqlite_connection, &ratings.rating_system)?;
+        }
+        let rating_kinds =
+            ingest::resolve_rating_kinds(&rusqlite_connection, ratings)?;
+        replay_all(&rusqlite_connection, &rating_kinds)
+    })?;
+
+    println!("Finished recomputing!");
+    Ok(())
+}
+
+/// Empties every rating table and replays all recorded sets through the
+/// given rating systems.
+pub fn replay_all(
+    rusqlite_connection: &RusqliteConnection,
+    rating_kinds: &[RatingKind],
+) -> Result<(), Error> {
+    // Every table is emptied and refilled by the replay, so nobody is left
+    // behind who is no longer rated in a table, such as members of teams
+    // in the singles tables once doubles are kept apart again.
+    for table in rusqlite_connection.select_rating_tables()? {
+        rusqlite_connection.clear_players(&table)?;
+    }
+
+    // Every table that any event is rated in, including the singles tables
+    // that members of teams are rated in if doubles are mixed into them.
+    let mut tables = vec![PLAYERS.to_owned()];
+    let mut period = RatingPeriod::new(rating_kinds);
+    for (game_name, is_team) in rusqlite_connection.select_game_names()? {
+        let event_rating =
+            EventRating::new(rusqlite_connection, &mut period, &game_name, is_team)?;
+        tables.extend(event_rating.tables());
+    }
+    tables.sort_unstable();
+    tables.dedup();
+
+    // Each tournament is one rating period, ended once all of its events
+    // have been replayed.
+    let events = group_events(rusqlite_connection.select_sets()?);
+    println!("Replaying {} events...", events.len());
+    let tournaments =
+        group_tournaments(events, &rusqlite_connection.select_event_tournaments()?);
+    let mut count = 0;
+    for events in tournaments {
+        let mut period = RatingPeriod::new(rating_kinds);
+        for sets in events {
+            count += 1;
+            let event_name =
+                format!("{} - {}", sets[0].game_name, sets[0].tournament_name);
+            println!("Processing event {}: {}", count, event_name);
+            replay_event(rusqlite_connection, &mut period, sets)
+                .context(|| format!("replaying {}", event_name))?;
+        }
+        period.end(rusqlite_connection)?;
+    }
+
+    ingest::update_rankings(rusqlite_connection, rating_kinds, &tables)?;
+
+    // Players are added back under the tag of their first set, so they are
+    // renamed to the tag they were seen under most recently.
+    for table in ingest::player_tables(rusqlite_connection)? {
+        rusqlite_connection.refresh_names(&table)?;
+    }
+
+    Ok(())
+}
+
+// Rates every set of a single event and wraps the event up the same way as
+// when it was first ingested.
+fn replay_event(
+    rusqlite_connection: &RusqliteConnection,
+    period: &mut RatingPeriod,
+    mut sets: Vec<SetsRow>,
+) -> Result<(), Error> {
+    let mut event_rating = EventRating::new(
+        rusqlite_connection,
+        period,
+        &sets[0].game_name,
+        sets[0].is_team,
+    )?;
+    let mut entrants = Vec::new();
+
+    for set in sets.iter_mut() {
+        for global_id in [set.player_one_global_id, set.player_two_global_id] {
+            if !entrants.contains(&global_id) {
+                entrants.push(global_id);
+            }
+        }
+
+        if ingest::is_dq(set) {
+            continue;
+        }
+        event_rating.rate_set(rusqlite_connection, set).context(|| {
+            format!(
+                "rating the set between {} and {} completed at {}",
+                set.player_one_name, set.player_two_name, set.set_time
+            )
+        })?;
+        rusqlite_connection.update_set_ratings(set)?;
+        period.record_set(set);
+    }
+
+    event_rating.finish(
+        rusqlite_connection,
+        period,
+        &entrants,
+        ingest::event_winner(sets.last()),
+    )
+}
+
+// Groups events, in the order of their first set, into the tournaments they
+// belong to, in the order of each tournament's first event. Events imported
+// before the events table existed are each a tournament of their own.
+fn group_tournaments(
+    events: Vec<Vec<SetsRow>>,
+    slugs: &HashMap<i32, String>,
+) -> Vec<Vec<Vec<SetsRow>>> {
+    let mut tournaments: Vec<Vec<Vec<SetsRow>>> = Vec::new();
+    let mut by_slug: HashMap<&str, usize> = HashMap::new();
+
+    for sets in events {
+        let slug = sets[0].event_id.and_then(|event_id| slugs.get(&event_id));
+        let index = match slug {
+            Some(slug) => *by_slug.entry(slug).or_insert(tournaments.len()),
+            None => tournaments.len(),
+        };
+
+        if index == tournaments.len() {
+            tournaments.push(Vec::new());
+        }
+        tournaments[index].push(sets);
+    }
+
+    tournaments
+}
+
+// Splits the sets, already in the order they were completed, into events.
+// Events are returned in the order of their first set.
+fn group_events(sets: Vec<SetsRow>) -> Vec<Vec<SetsRow>> {
+    let mut events: Vec<Vec<SetsRow>> = Vec::new();
+    let mut by_event_id: HashMap<i32, usize> = HashMap::new();
+    let mut by_name: HashMap<(String, String), (usize, i64)> = HashMap::new();
+
+    for set in sets {
+        let index = match set.event_id {
+            Some(event_id) => *by_event_id.entry(event_id).or_insert(events.len()),
+            None => {
+                let time = DateTime::parse_from_rfc3339(&set.set_time)
+                    .map(|time| time.timestamp())
+                    .unwrap_or_default();
+                let key = (set.tournament_name.clone(), set.game_name.clone());
+                let index = match by_name.get(&key) {
+                    Some(&(index, last_time))
+                        if time - last_time <= LEGACY_EVENT_GAP_SECS =>
+                    {
+                        index
+                    }
+                    _ => events.len(),
+                };
+                by_name.insert(key, (index, time));
+                index
+            }
+        };
+
+        if index == events.len() {
+            events.push(Vec::new());
+        }
+        events[index].push(set);
+    }
+
+    events
+}
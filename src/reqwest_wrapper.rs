@@ -1,89 +1,450 @@
-use reqwest::blocking::{Client, Response};
+use crate::error::{ApiError, Context, Error};
+use crate::fixtures::{self, Fixtures};
+use crate::json::PostResponse;
+use crate::rusqlite_wrapper::ResponseCache;
+use chrono::Utc;
+use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use serde::Serialize;
 use serde_json::Value;
-use smashgg_elo::get_input;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-const SMASH_URL: &str = "https://api.smash.gg/gql/alpha";
-const AUTH_PROMPT: &str = "See info about authentication here: https://developer.smash.gg/docs/authentication\nEnter your smash.gg authentication token: ";
-const SLUG_PROMPT: &str = "A tournament slug is of the form: https://smash.gg/tournament/[tournament slug]/...\nEnter the tournament slug to read data from: ";
+pub const DEFAULT_ENDPOINT: &str = "https://api.start.gg/gql/alpha";
 const MAX_ENTRANTS: i32 = 499;
 const MAX_SETS: i32 = 70;
+const MAX_TOURNAMENTS: i32 = 20;
+
+// Delay before the first retry of a failed request. Each retry after that
+// waits twice as long as the last, up to the maximum.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
 
 pub enum ContentType {
     Init,
-    Event,
-    Set,
     Info,
     Page,
+    Discover,
+}
+
+/// Settings for how requests are sent to smash.gg.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// URL of the GraphQL api requests are sent to.
+    pub endpoint: String,
+    /// Most requests sent within any window of time. smash.gg allows
+    /// around 80 requests per minute.
+    pub request_budget: u32,
+    pub budget_window: Duration,
+    /// How long to wait for a response before the request is retried.
+    pub timeout: Duration,
+    /// Times a failed request is retried before giving up.
+    pub max_retries: u32,
+    /// Most requests for pages of an event that are in flight at once.
+    pub workers: usize,
+    /// Where responses are recorded to or replayed from, if anywhere.
+    pub fixtures: Option<Fixtures>,
+    /// Path to the database that responses are cached in, if they are.
+    pub cache_path: Option<String>,
+    /// How long responses that may still change are cached for.
+    pub cache_ttl: Duration,
+}
+
+impl ClientOptions {
+    /// Returns true if responses are replayed from fixtures, in which case
+    /// no auth token is needed.
+    pub fn replays(&self) -> bool {
+        matches!(self.fixtures, Some(Fixtures::Replay(_)))
+    }
 }
 
 // A wrapper struct around a reqwest blocking Client. It contains the headers
-// and the json content needed to make a post request to smash.gg's api.
+// and the json content needed to make a post request to smash.gg's api, the
+// time of every request sent within the current budget window, and the
+// cache of earlier responses. The budget and the cache are shared between
+// the workers that fetch pages concurrently.
 pub struct ReqwestClient<'a> {
     client: Client,
     json_content: HashMap<&'a str, Value>,
+    options: ClientOptions,
+    sent: Mutex<VecDeque<Instant>>,
+    cache: Option<Mutex<ResponseCache>>,
+    cache_forever: bool,
+    refresh: bool,
+    // Page sizes that were lowered after smash.gg found a query too
+    // complex, by query. Later requests start from the lowered size.
+    page_sizes: HashMap<&'static str, i32>,
 }
 
-impl Default for ReqwestClient<'_> {
-    // Reads in user input to grab their smash.gg authentication token.
-    // Assigns the AUTHORIZATION header to Bearer [auth_token] and assigns the
+impl ReqwestClient<'_> {
+    // Takes in the user's smash.gg authentication token. Assigns the
+    // AUTHORIZATION header to Bearer [auth_token] and assigns the
     // CONTENT_TYPE header so we're taking in json on our post request.
-    fn default() -> Self {
+    pub fn new(auth_token: &str, options: ClientOptions) -> Result<Self, Error> {
         let mut headers = HeaderMap::new();
-        let mut auth_token: String = get_input(AUTH_PROMPT);
-        auth_token = "Bearer ".to_owned() + &auth_token;
+        let auth_token = "Bearer ".to_owned() + auth_token.trim();
 
         headers.insert(
             AUTHORIZATION,
-            HeaderValue::from_str(&auth_token).unwrap()
+            HeaderValue::from_str(&auth_token).map_err(|_| {
+                Error::InvalidInput(
+                    "The auth token contains invalid characters".to_string(),
+                )
+            })?,
         );
         headers.insert(
             CONTENT_TYPE,
             HeaderValue::from_static("application/json")
         );
 
-        ReqwestClient {
+        let cache = match (&options.cache_path, options.replays()) {
+            (Some(path), false) => Some(Mutex::new(
+                ResponseCache::open(path)
+                    .context(|| format!("opening the response cache in {}", path))?,
+            )),
+            _ => None,
+        };
+
+        Ok(ReqwestClient {
             client: reqwest::blocking::Client::builder()
                 .default_headers(headers)
-                .build()
-                .expect("Error in creating the reqwest client"),
+                .timeout(options.timeout)
+                .build()?,
             json_content: HashMap::new(),
-        }
+            options,
+            sent: Mutex::new(VecDeque::new()),
+            cache,
+            cache_forever: false,
+            refresh: false,
+            page_sizes: HashMap::new(),
+        })
     }
-}
 
-impl ReqwestClient<'_> {
-    pub fn new() -> Self {
-        ReqwestClient::default()
+    /// Sets whether responses received from now on are cached forever,
+    /// rather than for the cache's time to live. Only responses about
+    /// events that have completed, and so won't change, should be.
+    pub fn set_cache_forever(&mut self, forever: bool) {
+        self.cache_forever = forever;
+    }
+
+    /// Sets whether requests from now on skip the cache and are sent to
+    /// smash.gg, replacing the responses cached for them.
+    pub fn set_refresh(&mut self, refresh: bool) {
+        self.refresh = refresh;
     }
 
     // Sends a HTTP post request using the header and json fields in the
-    // struct and returns a reqwest response. This reqwest will later be
-    // parsed into json by other methods.
-    pub fn send_post(&self) -> Response {
-        self.client
-            .post(SMASH_URL)
-            .json(&self.json_content)
-            .send()
-            .expect("Error in sending post request")
+    // struct and parses the response as json.
+    pub fn send_post(&mut self) -> Result<PostResponse, Error> {
+        self.send(&self.json_content)
+    }
+
+    // Sends a request for every page of content at once, using up to the
+    // configured number of workers. The request budget is shared between
+    // the workers, so they are paced no faster than a single request at a
+    // time would be. Responses are returned in the order of the contents.
+    fn send_pages(
+        &self,
+        contents: &[Content],
+    ) -> Vec<Result<PostResponse, Error>> {
+        let requests: Vec<_> = contents.iter().map(request_json).collect();
+        let workers = self.options.workers.clamp(1, requests.len().max(1));
+        let next = AtomicUsize::new(0);
+
+        let mut responses: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut responses = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            match requests.get(i) {
+                                Some(request) => {
+                                    responses.push((i, self.send(request)))
+                                }
+                                None => return responses,
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle.join().expect("a page worker panicked")
+                })
+                .collect()
+        });
+
+        responses.sort_unstable_by_key(|(i, _)| *i);
+        responses.into_iter().map(|(_, response)| response).collect()
+    }
+
+    /// Requests every page of a paginated query of entrants or sets. The
+    /// first page says how many pages there are, and the rest are then
+    /// requested at once. The pages are split by the per_page variable of
+    /// the content, or by a smaller size if one was needed before. Whenever
+    /// smash.gg finds a page too complex, the page size is halved and the
+    /// pages are requested again. Responses are returned in page order.
+    pub fn send_all_pages(
+        &mut self,
+        content: &Content,
+    ) -> Result<Vec<PostResponse>, Error> {
+        let default_size = content.variables.per_page.unwrap_or(1);
+        loop {
+            let per_page = *self
+                .page_sizes
+                .get(content.query)
+                .unwrap_or(&default_size);
+            let page_content = |page| {
+                let mut content = content.clone();
+                content.variables.page = Some(page);
+                content.variables.per_page = Some(per_page);
+                content
+            };
+
+            let first = self.send(&request_json(&page_content(1)));
+            let (first, pages) = match first {
+                Ok(json) => {
+                    let pages = json.page_count(per_page)?;
+                    (Ok(json), pages)
+                }
+                Err(err) => (Err(err), 1),
+            };
+            let contents: Vec<_> = (2..pages + 1).map(page_content).collect();
+            let mut responses = vec![first];
+            responses.extend(self.send_pages(&contents));
+
+            let too_complex = responses
+                .iter()
+                .any(|response| matches!(response, Err(err) if err.is_complexity()));
+            if too_complex && per_page > 1 {
+                let smaller = per_page / 2;
+                println!(
+                    "Pages of {} were too complex for smash.gg, \
+                     retrying with pages of {}...",
+                    per_page, smaller
+                );
+                self.page_sizes.insert(content.query, smaller);
+                continue;
+            }
+
+            return (1..)
+                .zip(responses)
+                .map(|(page, response)| {
+                    response
+                        .context(|| format!("reading page {} of {}", page, pages))
+                })
+                .collect();
+        }
+    }
+
+    // Sends a request and parses the response as json. Requests are paced
+    // to stay within the request budget, and ones that fail because of the
+    // network, the rate limit or a server error are retried with
+    // exponential backoff.
+    fn send(&self, request: &HashMap<&str, Value>) -> Result<PostResponse, Error> {
+        let key = request_key(request);
+        if let Some(Fixtures::Replay(dir)) = &self.options.fixtures {
+            let (status, body) = fixtures::replay(dir, &key, query(request))?;
+            return parse_response(status, &body);
+        }
+
+        // Answer from the cache if the same request was made to the same
+        // endpoint before. When recording, every request is sent so every
+        // response is recorded.
+        let recording =
+            matches!(self.options.fixtures, Some(Fixtures::Record(_)));
+        let cache_key = cache_key(&self.options.endpoint, &key);
+        let cached = self.cache.as_ref().filter(|_| !recording && !self.refresh);
+        if let Some(cache) = cached {
+            let cached = lock(cache).get(&cache_key, Utc::now().timestamp())?;
+            if let Some(body) = cached {
+                return parse_response(200, &body);
+            }
+        }
+
+        let mut attempt = 0;
+        loop {
+            self.wait_for_budget();
+            let result = self.send_once(request, &key).and_then(|(status, body)| {
+                Ok((parse_response(status, &body)?, body))
+            });
+            match result {
+                Ok((json, body)) => {
+                    self.cache_response(&cache_key, &body)?;
+                    return Ok(json);
+                }
+                Err(err)
+                    if err.is_transient() && attempt < self.options.max_retries =>
+                {
+                    let delay = retry_delay(attempt);
+                    println!(
+                        "Request failed, retrying in {:.1}s ({} of {}): {}",
+                        delay.as_secs_f64(),
+                        attempt + 1,
+                        self.options.max_retries,
+                        err
+                    );
+                    thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Saves a successful response in the cache, if there is one.
+    fn cache_response(&self, key: &str, body: &str) -> Result<(), Error> {
+        if let Some(cache) = &self.cache {
+            let now = Utc::now().timestamp();
+            let expires_at = match self.cache_forever {
+                true => None,
+                false => Some(now + self.options.cache_ttl.as_secs() as i64),
+            };
+            lock(cache).insert(key, body, now, expires_at)?;
+        }
+
+        Ok(())
+    }
+
+    // Blocks until another request can be sent without going over the
+    // request budget, then records the request. The lock is held while
+    // waiting, so workers take their turns in the order they asked.
+    fn wait_for_budget(&self) {
+        let window = self.options.budget_window;
+        let budget = self.options.request_budget.max(1) as usize;
+        let mut sent = lock(&self.sent);
+
+        while let Some(&oldest) = sent.front() {
+            if oldest.elapsed() >= window {
+                sent.pop_front();
+            } else if sent.len() >= budget {
+                thread::sleep(window.saturating_sub(oldest.elapsed()));
+            } else {
+                break;
+            }
+        }
+        sent.push_back(Instant::now());
+    }
+
+    // Sends a single request, recording the response if asked to. Returns
+    // the HTTP status and body of the response.
+    fn send_once(
+        &self,
+        request: &HashMap<&str, Value>,
+        key: &str,
+    ) -> Result<(u16, String), Error> {
+        let response = self
+            .client
+            .post(&self.options.endpoint)
+            .json(request)
+            .send()?;
+        let status = response.status().as_u16();
+        let body = response.text()?;
+
+        if let Some(Fixtures::Record(dir)) = &self.options.fixtures {
+            fixtures::record(
+                dir,
+                key,
+                query(request),
+                variables(request),
+                status,
+                &body,
+            )?;
+        }
+        Ok((status, body))
     }
 
     // Changes the json content in the HTTP post request. Only changes the
     // values for query and the values for variables.
     pub fn construct_json(&mut self, content: &Content) {
-        self.json_content
-            .insert("query", Value::from(content.query));
-        self.json_content
-            .insert("variables", serde_json::json!(content.variables));
+        self.json_content = request_json(content);
+    }
+}
+
+// The json sent in the HTTP post request for the given content.
+fn request_json(content: &Content) -> HashMap<&'static str, Value> {
+    HashMap::from([
+        ("query", Value::from(content.query)),
+        ("variables", serde_json::json!(content.variables)),
+    ])
+}
+
+fn query<'a>(request: &'a HashMap<&str, Value>) -> &'a str {
+    request
+        .get("query")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn variables<'a>(request: &'a HashMap<&str, Value>) -> &'a Value {
+    request.get("variables").unwrap_or(&Value::Null)
+}
+
+// A hash of the query and variables of a request, identifying the request
+// regardless of the order its variables are serialized in.
+fn request_key(request: &HashMap<&str, Value>) -> String {
+    let digest = Sha256::new()
+        .chain_update(query(request))
+        .chain_update([0])
+        .chain_update(variables(request).to_string())
+        .finalize();
+
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The key a response is cached under. Responses from different endpoints,
+// such as a local stand-in and smash.gg itself, are kept apart.
+fn cache_key(endpoint: &str, request_key: &str) -> String {
+    let digest = Sha256::new()
+        .chain_update(endpoint)
+        .chain_update([0])
+        .chain_update(request_key)
+        .finalize();
+
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Locks a mutex shared between page workers. A worker that panicked while
+// holding the lock leaves nothing half written, so the lock is still used.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Parses the body of a response as json. Errors reported by smash.gg are
+// returned along with the HTTP status.
+fn parse_response(status: u16, body: &str) -> Result<PostResponse, Error> {
+    match serde_json::from_str::<PostResponse>(body) {
+        Ok(json) => json.check(status),
+        Err(err) => {
+            let message = match body.trim().is_empty() {
+                true => format!("Unreadable response: {}", err),
+                false => body.trim().chars().take(200).collect(),
+            };
+            Err(Error::Response(ApiError::new(status, vec![message])))
+        }
     }
 }
 
+// The delay before retrying a request for the given attempt. Up to half the
+// delay again is added at random, so retries don't all land at once.
+fn retry_delay(attempt: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(RETRY_MAX_DELAY);
+    delay + delay.mul_f64(fastrand::f64() / 2.0)
+}
+
 // Struct that contains all of the necessary information needed to get the
 // right data back from smash.gg's api. This includes the graphql query and
 // the relevant variables that will be converted into json to send.
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct Content {
     pub query: &'static str,
     pub variables: Variables,
@@ -104,20 +465,12 @@ impl Content {
     }
 
     // Changes the query and the per_page variable sent through the HTTP post
-    // request.
+    // request. The tournament slug must already be set for ContentType::Init.
     pub fn edit_content(&mut self, enum_type: ContentType) {
         (self.query, self.variables.per_page) = match enum_type {
-            ContentType::Init => {
-                self.variables.tournament_slug = Some(get_input(SLUG_PROMPT));
-                (include_str!("query/tourney_event_query.graphql"), None)
-            }
-            ContentType::Event => (
-                include_str!("query/entrant_page_query.graphql"),
-                Some(MAX_ENTRANTS),
-            ),
-            ContentType::Set => (
-                include_str!("query/sets_page_query.graphql"),
-                Some(MAX_SETS),
+            ContentType::Init => (
+                include_str!("query/tourney_event_query.graphql"),
+                None,
             ),
             ContentType::Info => (
                 include_str!("query/sets_info_query.graphql"),
@@ -127,18 +480,47 @@ impl Content {
                 include_str!("query/entrant_info_query.graphql"),
                 Some(MAX_ENTRANTS),
             ),
+            ContentType::Discover => (
+                include_str!("query/tournaments_query.graphql"),
+                Some(MAX_TOURNAMENTS),
+            ),
         };
     }
 }
 
 // Struct that contains the variables that we send with our HTTP post request.
 // Not all variables need to be present with each request.
-#[derive(Default, Serialize)]
+#[derive(Default, Serialize, Clone)]
 pub struct Variables {
     pub tournament_slug: Option<String>,
     pub event_id: Option<i32>,
     pub page: Option<i32>,
     pub per_page: Option<i32>,
+    // Filters only used when discovering tournaments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub videogame_ids: Option<Vec<i32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub addr_state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<LocationFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after_date: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before_date: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_online_events: Option<bool>,
+}
+
+// Limits discovered tournaments to those within a distance of a point. The
+// point is given as "latitude,longitude" and the distance with its unit,
+// such as "50mi" or "80km".
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationFilter {
+    pub distance_from: String,
+    pub distance: String,
 }
 
 impl Variables {
@@ -1,7 +1,47 @@
-use rusqlite::{params, Connection, Error};
+use crate::glicko::GlickoRating;
+use crate::json::{is_guest, PlayerProfile};
+use rusqlite::{params, Connection, Error, OptionalExtension};
 use smashgg_elo::clean_string;
 use std::collections::HashMap;
 
+// Columns added to the player tables after their initial release. Tables
+// created by older versions are migrated by adding any that are missing.
+const ADDED_PLAYER_COLUMNS: [(&str, &str); 4] = [
+    ("glicko_rating", "REAL DEFAULT 1500.0 NOT NULL"),
+    ("glicko_rd", "REAL DEFAULT 350.0 NOT NULL"),
+    ("glicko_volatility", "REAL DEFAULT 0.06 NOT NULL"),
+    ("verified", "INTEGER DEFAULT 1 NOT NULL"),
+];
+
+// Columns added to the sets table after its initial release.
+const ADDED_SET_COLUMNS: [(&str, &str); 2] = [
+    ("event_id", "INTEGER"),
+    ("is_team", "INTEGER DEFAULT 0 NOT NULL"),
+];
+
+// How a profile seen at one time is combined with the one already saved.
+// What was seen at the latest event wins, except that details from an
+// account aren't lost when the player is next seen without them.
+const PROFILE_UPSERT: &str = "ON CONFLICT (global_id) DO UPDATE SET
+    prefix = CASE WHEN excluded.seen_at >= seen_at
+        THEN excluded.prefix ELSE prefix END,
+    slug = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.slug, slug) ELSE coalesce(slug, excluded.slug) END,
+    country = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.country, country) ELSE coalesce(country, excluded.country) END,
+    state = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.state, state) ELSE coalesce(state, excluded.state) END,
+    city = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.city, city) ELSE coalesce(city, excluded.city) END,
+    twitter = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.twitter, twitter) ELSE coalesce(twitter, excluded.twitter) END,
+    discord = CASE WHEN excluded.seen_at >= seen_at
+        THEN coalesce(excluded.discord, discord) ELSE coalesce(discord, excluded.discord) END,
+    seen_at = max(seen_at, excluded.seen_at)";
+
+// Columns added to the events table after its initial release.
+const ADDED_EVENT_COLUMNS: [(&str, &str); 1] = [("game_id", "INTEGER")];
+
 // Wrapper struct representing a connection to a sqlite database.
 pub struct RusqliteConnection {
     conn: Connection,
@@ -9,7 +49,9 @@ pub struct RusqliteConnection {
 
 // Struct that represents a row in the players table. This contains all of the
 // data and statistics of a player's performance in a tournament over time.
-#[derive(Debug)]
+// Not every column is read back by the binary yet.
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub struct PlayersRow {
     pub global_id: i32,
     pub name: String,
@@ -21,12 +63,43 @@ pub struct PlayersRow {
     pub win_loss_ratio: f64,
     pub num_tournaments: i32,
     pub tournament_wins: i32,
+    pub glicko_rating: f64,
+    pub glicko_rd: f64,
+    pub glicko_volatility: f64,
+}
+
+impl PlayersRow {
+    /// Records the games a player won and lost in a set and updates their
+    /// win/loss statistics.
+    pub fn record_games(&mut self, wins: i32, losses: i32) {
+        self.num_games += wins + losses;
+        self.wins += wins;
+        self.losses += losses;
+        self.win_loss_ratio = self.wins as f64 / self.num_games as f64;
+    }
+
+    pub fn glicko(&self) -> GlickoRating {
+        GlickoRating {
+            rating: self.glicko_rating,
+            rd: self.glicko_rd,
+            volatility: self.glicko_volatility,
+        }
+    }
+
+    pub fn set_glicko(&mut self, rating: &GlickoRating) {
+        self.glicko_rating = rating.rating;
+        self.glicko_rd = rating.rd;
+        self.glicko_volatility = rating.volatility;
+    }
 }
 
 // Struct that represents a row in the sets table. This contains all of the
 // details of a set that happened between two players in a tournament, and the
-// changes to the elo that happened as a result of the set.
+// changes to the elo that happened as a result of the set. In a set between
+// teams, the global ids and names are those of the teams.
+#[derive(Clone)]
 pub struct SetsRow {
+    pub id: i64,
     pub player_one_global_id: i32,
     pub player_one_name: String,
     pub player_one_elo: f64,
@@ -40,11 +113,14 @@ pub struct SetsRow {
     pub tournament_name: String,
     pub game_name: String,
     pub set_time: String,
+    pub event_id: Option<i32>,
+    pub is_team: bool,
 }
 
 impl Default for SetsRow {
     fn default() -> Self {
         SetsRow {
+            id: 0,
             player_one_global_id: -1,
             player_one_name: "Player 1".to_string(),
             player_one_elo: 0.0,
@@ -58,10 +134,77 @@ impl Default for SetsRow {
             tournament_name: "Default Tournament".to_string(),
             game_name: "Default Game".to_string(),
             set_time: "".to_string(),
+            event_id: None,
+            is_team: false,
         }
     }
 }
 
+// Struct that represents a row in the events table. This records an event
+// that has been imported into the database.
+pub struct EventsRow {
+    pub event_id: i32,
+    pub tournament_slug: String,
+    pub event_name: String,
+    pub game_name: String,
+    /// The smash.gg id of the game, unknown for events imported before it
+    /// was recorded.
+    pub game_id: Option<i32>,
+    pub imported_at: String,
+    pub set_count: i32,
+}
+
+// Columns selected for a PlayersRow, in the order read by player_from_row.
+const PLAYER_COLUMNS: &str = "global_id, name, rank, elo, num_games, wins,
+    losses, win_loss_ratio, num_tournaments, tournament_wins, glicko_rating,
+    glicko_rd, glicko_volatility";
+
+fn player_from_row(row: &rusqlite::Row) -> Result<PlayersRow, Error> {
+    Ok(PlayersRow {
+        global_id: row.get(0)?,
+        name: row.get(1)?,
+        rank: row.get(2)?,
+        elo: row.get(3)?,
+        num_games: row.get(4)?,
+        wins: row.get(5)?,
+        losses: row.get(6)?,
+        win_loss_ratio: row.get(7)?,
+        num_tournaments: row.get(8)?,
+        tournament_wins: row.get(9)?,
+        glicko_rating: row.get(10)?,
+        glicko_rd: row.get(11)?,
+        glicko_volatility: row.get(12)?,
+    })
+}
+
+// Columns selected for a SetsRow, in the order read by set_from_row.
+const SET_COLUMNS: &str = "id, player_one_global_id, player_one_name,
+    player_one_elo, player_one_score, player_one_elo_delta,
+    player_two_global_id, player_two_name, player_two_elo, player_two_score,
+    player_two_elo_delta, tournament_name, game_name, set_time, event_id,
+    is_team";
+
+fn set_from_row(row: &rusqlite::Row) -> Result<SetsRow, Error> {
+    Ok(SetsRow {
+        id: row.get(0)?,
+        player_one_global_id: row.get(1)?,
+        player_one_name: row.get(2)?,
+        player_one_elo: row.get(3)?,
+        player_one_score: row.get(4)?,
+        player_one_elo_delta: row.get(5)?,
+        player_two_global_id: row.get(6)?,
+        player_two_name: row.get(7)?,
+        player_two_elo: row.get(8)?,
+        player_two_score: row.get(9)?,
+        player_two_elo_delta: row.get(10)?,
+        tournament_name: row.get(11)?,
+        game_name: row.get(12)?,
+        set_time: row.get(13)?,
+        event_id: row.get(14)?,
+        is_team: row.get(15)?,
+    })
+}
+
 // Wrapper struct for the ease of working with an iterator when updating elo
 // and updating statistics such as tournament count.
 #[derive(Debug)]
@@ -69,30 +212,10 @@ pub struct ItrStruct {
     pub itr_int: i32,
 }
 
-impl Default for RusqliteConnection {
-    fn default() -> Self {
+impl RusqliteConnection {
+    pub fn new(path: &str) -> Result<Self, Error> {
         // Initialize connection to the sqlite db.
-        let conn =
-            Connection::open("./database/smashhgg.db3")
-            .expect("Connecting to database failed");
-
-        // Initialize the player table if there is none.
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS players (
-                global_id               INTEGER NOT NULL PRIMARY KEY UNIQUE,
-                name             TEXT NOT NULL,
-                rank             INTEGER DEFAULT 0 NOT NULL,
-                elo              REAL DEFAULT 1500.0 NOT NULL,
-                num_games        INTEGER DEFAULT 0 NOT NULL,
-                wins             INTEGER DEFAULT 0 NOT NULL,
-                losses           INTEGER DEFAULT 0 NOT NULL,
-                win_loss_ratio   REAL DEFAULT 0 NOT NULL,
-                num_tournaments  INTEGER DEFAULT 0 NOT NULL,
-                tournament_wins  INTEGER DEFAULT 0 NOT NULL
-            )",
-            [],
-        )
-        .expect("Creating player table failed");
+        let conn = Connection::open(path)?;
 
         // Initialize the set history table if there is none.
         conn.execute(
@@ -110,25 +233,184 @@ impl Default for RusqliteConnection {
                 player_two_elo_delta    REAL NOT NULL,
                 tournament_name         TEXT NOT NULL,
                 game_name               TEST NOT NULL,
-                set_time                TEXT NOT NULL
+                set_time                TEXT NOT NULL,
+                event_id                INTEGER,
+                is_team                 INTEGER DEFAULT 0 NOT NULL
             )",
             [],
-        )
-        .expect("Creating sets table failed");
-        println!("Connected to database at database/smashgg.db3");
+        )?;
+
+        // Initialize the settings table that records how this database was
+        // set up, such as which rating system it uses.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key     TEXT NOT NULL PRIMARY KEY,
+                value   TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Initialize the table of events that have been imported, so the same
+        // event is never counted twice.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS events (
+                event_id        INTEGER NOT NULL PRIMARY KEY,
+                tournament_slug TEXT NOT NULL,
+                event_name      TEXT NOT NULL,
+                game_name       TEXT NOT NULL,
+                imported_at     TEXT NOT NULL,
+                set_count       INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        // Initialize the tables of teams. A team is the same set of players,
+        // whatever its name, and each entrant of a team event is mapped to
+        // the team it was.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS teams (
+                team_id     INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+                member_ids  TEXT NOT NULL UNIQUE,
+                name        TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS team_members (
+                team_id     INTEGER NOT NULL,
+                global_id   INTEGER NOT NULL,
+                name        TEXT NOT NULL,
+                PRIMARY KEY (team_id, global_id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS event_teams (
+                event_id    INTEGER NOT NULL,
+                entrant_id  INTEGER NOT NULL,
+                team_id     INTEGER NOT NULL,
+                PRIMARY KEY (event_id, entrant_id)
+            )",
+            [],
+        )?;
+
+        // Initialize the table of every tag each player has entered under,
+        // and when it was first and last seen.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS aliases (
+                global_id   INTEGER NOT NULL,
+                name        TEXT NOT NULL,
+                first_seen  TEXT NOT NULL,
+                last_seen   TEXT NOT NULL,
+                PRIMARY KEY (global_id, name)
+            )",
+            [],
+        )?;
+
+        // Initialize the table of each player's prefix, location and social
+        // handles, as of the latest event they were seen at.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS profiles (
+                global_id   INTEGER NOT NULL PRIMARY KEY,
+                prefix      TEXT,
+                slug        TEXT,
+                country     TEXT,
+                state       TEXT,
+                city        TEXT,
+                twitter     TEXT,
+                discord     TEXT,
+                seen_at     TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Initialize the table of each player's rank within the regions
+        // defined in the config file, for each table they are rated in.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS regional_ranks (
+                region      TEXT NOT NULL,
+                table_name  TEXT NOT NULL,
+                global_id   INTEGER NOT NULL,
+                rank        INTEGER NOT NULL,
+                PRIMARY KEY (region, table_name, global_id)
+            )",
+            [],
+        )?;
+
+        // Initialize the table of players merged into another, so their
+        // sets keep going to the player they were merged into.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS merged_players (
+                global_id       INTEGER NOT NULL PRIMARY KEY,
+                merged_into     INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        println!("Connected to database at {}", path);
 
-        RusqliteConnection { conn }
+        // Initialize the player table if there is none, and migrate every
+        // rating table made by an older version.
+        let rusqlite_connection = RusqliteConnection { conn };
+        rusqlite_connection.add_missing_columns("sets", &ADDED_SET_COLUMNS)?;
+        rusqlite_connection.add_missing_columns("events", &ADDED_EVENT_COLUMNS)?;
+        rusqlite_connection.create_table("players")?;
+        for table in rusqlite_connection.select_rating_tables()? {
+            rusqlite_connection.add_missing_columns(&table, &ADDED_PLAYER_COLUMNS)?;
+        }
+        Ok(rusqlite_connection)
     }
-}
 
-impl RusqliteConnection {
-    pub fn new() -> Self {
-        RusqliteConnection::default()
+    /// Reads a value from the settings table.
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>, Error> {
+        self.conn
+            .query_row(
+                "SELECT value FROM settings WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Writes a value to the settings table, replacing any previous value.
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Runs the given function inside a transaction. Everything it writes is
+    /// committed if it succeeds and rolled back if it returns an error. If
+    /// it panics, the transaction is never committed and sqlite rolls it
+    /// back once the connection closes.
+    pub fn transaction<T, E: From<Error>>(
+        &self,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.conn.execute_batch("BEGIN")?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute_batch("COMMIT")?;
+                Ok(value)
+            }
+            Err(err) => {
+                self.conn.execute_batch("ROLLBACK")?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns true if any set has been recorded in the database.
+    pub fn has_sets(&self) -> Result<bool, Error> {
+        self.conn
+            .query_row("SELECT EXISTS (SELECT 1 FROM sets)", [], |row| row.get(0))
     }
 
     /// Given a table name, create a table in the sqlite database if it doesn't
-    /// exist. This is primarily used to generate tables for different games.
-    pub fn create_table(&self, table_name: &String) {
+    /// exist. This is used for the players table and to generate tables for
+    /// different games. Tables from older versions get any missing columns.
+    pub fn create_table(&self, table_name: &str) -> Result<(), Error> {
         // Initialize a game table if there is none.
         let table_stmt = format!("CREATE TABLE IF NOT EXISTS {} (
             global_id        INTEGER NOT NULL PRIMARY KEY UNIQUE,
@@ -140,69 +422,114 @@ impl RusqliteConnection {
             losses           INTEGER DEFAULT 0 NOT NULL,
             win_loss_ratio   REAL DEFAULT 0 NOT NULL,
             num_tournaments  INTEGER DEFAULT 0 NOT NULL,
-            tournament_wins  INTEGER DEFAULT 0 NOT NULL
+            tournament_wins  INTEGER DEFAULT 0 NOT NULL,
+            glicko_rating    REAL DEFAULT 1500.0 NOT NULL,
+            glicko_rd        REAL DEFAULT 350.0 NOT NULL,
+            glicko_volatility REAL DEFAULT 0.06 NOT NULL,
+            verified         INTEGER DEFAULT 1 NOT NULL
         )", clean_string(table_name));
-        self.conn
-            .execute(table_stmt.as_str(), [])
-            .expect("Creating a game table failed");
+        self.conn.execute(table_stmt.as_str(), [])?;
+
+        // Add any columns missing from a table made by an older version.
+        self.add_missing_columns(table_name, &ADDED_PLAYER_COLUMNS)
+    }
+
+    // Migrates a table created by an older version by adding any of the
+    // given columns that it is missing.
+    fn add_missing_columns(
+        &self,
+        table_name: &str,
+        added: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        let columns_stmt =
+            format!("PRAGMA table_info({})", clean_string(table_name));
+        let mut stmt = self.conn.prepare(&columns_stmt)?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (column, definition) in added {
+            if !columns.iter().any(|name| name == column) {
+                let alter_stmt = format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    clean_string(table_name),
+                    column,
+                    definition
+                );
+                self.conn.execute(&alter_stmt, [])?;
+            }
+        }
+
+        Ok(())
     }
 
     // Given a global id, and the player name, the function searches the
     // database for any existing record of the player participating in a
-    // tournament. If no such record exists, it will create one.
+    // tournament. If no such record exists, it will create one. Guests
+    // without a smash.gg account are marked as unverified.
     pub fn select_player(
         &self,
         global_id: i32,
         name: &String,
-        table_name: &String,
+        table_name: &str,
     ) -> Result<PlayersRow, Error> {
         // If the player does not exist in the database, create a default
         // record for the player in the sqlite database.
         let insert_stmt = format!(
-            "INSERT OR IGNORE INTO {} (global_id, name) VALUES (?1, ?2)",
+            "INSERT OR IGNORE INTO {} (global_id, name, verified)
+            VALUES (?1, ?2, ?3)",
             clean_string(table_name)
         );
-        self.conn
-            .execute(insert_stmt.as_str(), params![global_id, name])?;
+        self.conn.execute(
+            insert_stmt.as_str(),
+            params![global_id, name, !is_guest(global_id)],
+        )?;
 
         // Find the row in the player table that matches to the id. Once found
         // create a PlayerRow object to use.
         let select_stmt = format!(
-            "SELECT * FROM {} WHERE global_id = ?1",
+            "SELECT {} FROM {} WHERE global_id = ?1",
+            PLAYER_COLUMNS,
             clean_string(table_name)
         );
-        let mut stmt = self.conn.prepare(select_stmt.as_str())?;
-        let player_iter = stmt.query_map(params![global_id], |row| {
-            Ok(PlayersRow {
-                global_id,
-                name: row.get(1)?,
-                rank: row.get(2)?,
-                elo: row.get(3)?,
-                num_games: row.get(4)?,
-                wins: row.get(5)?,
-                losses: row.get(6)?,
-                win_loss_ratio: row.get(7)?,
-                num_tournaments: row.get(8)?,
-                tournament_wins: row.get(9)?,
-            })
-        })?;
+        self.conn
+            .query_row(select_stmt.as_str(), params![global_id], player_from_row)
+    }
+
+    /// Selects every player in a table.
+    pub fn select_players(
+        &self,
+        table_name: &str,
+    ) -> Result<Vec<PlayersRow>, Error> {
+        let select_stmt = format!(
+            "SELECT {} FROM {}",
+            PLAYER_COLUMNS,
+            clean_string(table_name)
+        );
+        let mut stmt = self.conn.prepare(&select_stmt)?;
+        let player_iter = stmt.query_map([], player_from_row)?;
 
-        player_iter
-            .last()
-            .expect("Getting a player from the database failed")
+        player_iter.collect()
     }
 
     // Updates player information in the database after elo calculations have
     // been made.
-    pub fn update_player(&self, player: &PlayersRow, table_name: &String) {
+    pub fn update_player(
+        &self,
+        player: &PlayersRow,
+        table_name: &str,
+    ) -> Result<(), Error> {
         let update_stmt = format!(
             "UPDATE {} SET
                 elo = ?1,
                 num_games = ?2, 
                 wins = ?3,
                 losses = ?4,
-                win_loss_ratio = ?5
-            WHERE global_id = ?6",
+                win_loss_ratio = ?5,
+                glicko_rating = ?6,
+                glicko_rd = ?7,
+                glicko_volatility = ?8
+            WHERE global_id = ?9",
             clean_string(table_name)
         );
         self.conn
@@ -214,15 +541,19 @@ impl RusqliteConnection {
                     player.wins,
                     player.losses,
                     player.win_loss_ratio,
+                    player.glicko_rating,
+                    player.glicko_rd,
+                    player.glicko_volatility,
                     player.global_id
                 ],
-            )
-            .expect("Updating player info failed");
+            )?;
+
+        Ok(())
     }
 
     // Records the result of the set and any information regarding changes in
-    // elo into the database.
-    pub fn insert_set(&self, match_info: SetsRow) {
+    // elo into the database. Returns the id of the new row.
+    pub fn insert_set(&self, match_info: &SetsRow) -> Result<i64, Error> {
         self.conn
             .execute(
                 "INSERT INTO sets (player_one_global_id,
@@ -237,8 +568,11 @@ impl RusqliteConnection {
                     player_two_elo_delta,
                     tournament_name,
                     game_name,
-                    set_time)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                    set_time,
+                    event_id,
+                    is_team)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+                    ?14, ?15)",
                 params![
                     match_info.player_one_global_id,
                     match_info.player_one_name,
@@ -252,23 +586,27 @@ impl RusqliteConnection {
                     match_info.player_two_elo_delta,
                     match_info.tournament_name,
                     match_info.game_name,
-                    match_info.set_time
+                    match_info.set_time,
+                    match_info.event_id,
+                    match_info.is_team
                 ],
-            )
-            .expect("Inserting match into database failed");
+            )?;
+
+        Ok(self.conn.last_insert_rowid())
     }
 
     // Simply selects all of the players who have at least one completed set
-    // and updates the elo rankings in the database.
+    // and updates the rankings in the database using the given rating column.
     pub fn update_ranking(
         &self,
-        table_name: &String
+        table_name: &str,
+        rating_column: &str,
     ) -> Result<(), rusqlite::Error> {
-        // Select all players in the database and order by elo.
-        let mut count = 1;
+        // Select all players in the database and order by rating.
         let rank_stmt = format!(
-            "SELECT global_id FROM {} ORDER BY elo DESC",
-            clean_string(table_name)
+            "SELECT global_id FROM {} ORDER BY {} DESC",
+            clean_string(table_name),
+            rating_column
         );
         let mut stmt = self.conn.prepare(&rank_stmt)?;
         let rank_iter = stmt.query_map([], |row| {
@@ -282,16 +620,9 @@ impl RusqliteConnection {
             "UPDATE {} SET rank = ?1 WHERE global_id =?2",
             clean_string(table_name)
         );
-        for player_id in rank_iter {
+        for (count, player_id) in (1..).zip(rank_iter) {
             let mut stmt = self.conn.prepare(&ranking_stmt.to_string())?;
-
-            match player_id {
-                Ok(player) => {
-                    stmt.execute(params![count, player.itr_int])?;
-                    count += 1;
-                }
-                Err(err) => println!("Error updating rankings: {}", err),
-            }
+            stmt.execute(params![count, player_id?.itr_int])?;
         }
 
         Ok(())
@@ -299,45 +630,35 @@ impl RusqliteConnection {
 
     pub fn increment_count(
         &self,
-        player_map: &HashMap<i32, (String, i32)>,
-        table_name: &String,
+        global_ids: &[i32],
+        table_name: &str,
     ) -> Result<(), rusqlite::Error> {
         // For each player in the tournament, grab the number of tournaments
         // that they participated in.
-        for player in player_map.values() {
+        for global_id in global_ids {
             let incr_stmt = format!(
                 "SELECT num_tournaments FROM {} WHERE global_id = {}",
                 clean_string(table_name),
-                player.1
+                global_id
             );
             let mut stmt = self.conn.prepare(&incr_stmt.to_string())?;
-            let mut incr_iter = stmt
+            let incr_iter = stmt
                 .query_map([], |row| {
                     Ok(ItrStruct {
                         itr_int: row.get(0)?,
                     })
-                })?
-                .peekable();
+                })?;
 
             // If the player exists in the sqlite database, increment the
             // number of tournaments that they have.
-            if incr_iter.peek().is_some() {
-                match incr_iter
-                    .last()
-                    .expect("Iterator error: No data for num_tournaments")
-                {
-                    Ok(num_tour) => {
-                        let update_stmt = format!(
-                            "UPDATE {} SET num_tournaments = ?1 WHERE global_id = ?2",
-                            clean_string(table_name)
-                        );
-                        stmt = self.conn.prepare(&update_stmt.to_string())?;
-                        stmt.execute(params![num_tour.itr_int + 1, player.1])?;
-                    }
-                    Err(err) => {
-                        panic!("Error updating number of tournaments: {}", err)
-                    }
-                }
+            if let Some(num_tour) = incr_iter.last() {
+                let num_tour = num_tour?;
+                let update_stmt = format!(
+                    "UPDATE {} SET num_tournaments = ?1 WHERE global_id = ?2",
+                    clean_string(table_name)
+                );
+                stmt = self.conn.prepare(&update_stmt.to_string())?;
+                stmt.execute(params![num_tour.itr_int + 1, global_id])?;
             }
         }
 
@@ -350,7 +671,7 @@ impl RusqliteConnection {
     pub fn assign_winner(
         &self,
         global_id: i32,
-        table_name: &String,
+        table_name: &str,
     ) -> Result<(), rusqlite::Error> {
         // Select the number of wins that the tournament winner has
         let win_stmt = format!(
@@ -371,16 +692,542 @@ impl RusqliteConnection {
             clean_string(table_name)
         );
         let mut stmt = self.conn.prepare(&winner_stmt)?;
-        match win_iter
-            .last()
-            .expect("Iterator error: No data found for tournament_wins")
-        {
-            Ok(winner_struct) => {
-                stmt.execute(params![winner_struct.itr_int + 1, global_id])?;
-            }
-            Err(err) => panic!("Matching error: {}", err),
+        let winner_struct = win_iter.last().ok_or(Error::QueryReturnedNoRows)??;
+        stmt.execute(params![winner_struct.itr_int + 1, global_id])?;
+
+        Ok(())
+    }
+
+    /// Selects every set in the order they were completed. Sets completed at
+    /// the same time keep the order they were inserted in.
+    pub fn select_sets(&self) -> Result<Vec<SetsRow>, Error> {
+        let select_stmt = format!(
+            "SELECT {} FROM sets ORDER BY set_time, id",
+            SET_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&select_stmt)?;
+        let set_iter = stmt.query_map([], set_from_row)?;
+
+        set_iter.collect()
+    }
+
+    /// Selects the name of every game that has a set recorded, along with
+    /// whether the sets were between teams. A game played both in singles
+    /// and in teams is listed twice.
+    pub fn select_game_names(&self) -> Result<Vec<(String, bool)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT game_name, is_team FROM sets")?;
+        let game_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        game_iter.collect()
+    }
+
+    /// Finds the team made up of the given players, creating it if they
+    /// haven't played as a team before, and returns its id. The team takes
+    /// the given name, which may have changed since it last played.
+    pub fn select_team(
+        &self,
+        name: &str,
+        members: &[(String, i32)],
+    ) -> Result<i32, Error> {
+        let mut member_ids: Vec<i32> =
+            members.iter().map(|(_, global_id)| *global_id).collect();
+        member_ids.sort_unstable();
+        let member_ids = member_ids
+            .iter()
+            .map(|global_id| global_id.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        self.conn.execute(
+            "INSERT INTO teams (member_ids, name) VALUES (?1, ?2)
+            ON CONFLICT (member_ids) DO UPDATE SET name = excluded.name",
+            params![member_ids, name],
+        )?;
+        let team_id: i32 = self.conn.query_row(
+            "SELECT team_id FROM teams WHERE member_ids = ?1",
+            params![member_ids],
+            |row| row.get(0),
+        )?;
+
+        for (member_name, global_id) in members {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO team_members (team_id, global_id, name)
+                VALUES (?1, ?2, ?3)",
+                params![team_id, global_id, member_name],
+            )?;
         }
 
+        Ok(team_id)
+    }
+
+    /// Selects the name and global id of every member of a team.
+    pub fn select_team_members(
+        &self,
+        team_id: i32,
+    ) -> Result<Vec<(String, i32)>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT name, global_id FROM team_members WHERE team_id = ?1
+            ORDER BY global_id",
+        )?;
+        let member_iter =
+            stmt.query_map(params![team_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        member_iter.collect()
+    }
+
+    /// Records which team an entrant of an event was.
+    pub fn insert_event_team(
+        &self,
+        event_id: i32,
+        entrant_id: i32,
+        team_id: i32,
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO event_teams (event_id, entrant_id, team_id)
+            VALUES (?1, ?2, ?3)",
+            params![event_id, entrant_id, team_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// Overwrites the ratings recorded for a set, used when sets are replayed.
+    pub fn update_set_ratings(&self, set: &SetsRow) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE sets SET
+                player_one_elo = ?1,
+                player_one_elo_delta = ?2,
+                player_two_elo = ?3,
+                player_two_elo_delta = ?4
+            WHERE id = ?5",
+            params![
+                set.player_one_elo,
+                set.player_one_elo_delta,
+                set.player_two_elo,
+                set.player_two_elo_delta,
+                set.id
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Selects the slug of the tournament of every imported event, keyed by
+    /// the event's id.
+    pub fn select_event_tournaments(&self) -> Result<HashMap<i32, String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT event_id, tournament_slug FROM events")?;
+        let event_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        event_iter.collect()
+    }
+
+    /// Selects the record of an imported event, if it has been imported.
+    pub fn select_event(&self, event_id: i32) -> Result<Option<EventsRow>, Error> {
+        self.conn
+            .query_row(
+                "SELECT event_id, tournament_slug, event_name, game_name,
+                    imported_at, set_count, game_id
+                FROM events WHERE event_id = ?1",
+                params![event_id],
+                |row| {
+                    Ok(EventsRow {
+                        event_id: row.get(0)?,
+                        tournament_slug: row.get(1)?,
+                        event_name: row.get(2)?,
+                        game_name: row.get(3)?,
+                        imported_at: row.get(4)?,
+                        set_count: row.get(5)?,
+                        game_id: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    /// Records that an event has been imported.
+    pub fn insert_event(&self, event: &EventsRow) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO events (event_id, tournament_slug,
+                event_name, game_name, imported_at, set_count, game_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                event.event_id,
+                event.tournament_slug,
+                event.event_name,
+                event.game_name,
+                event.imported_at,
+                event.set_count,
+                event.game_id
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records that a player entered under a tag at the given time, widening
+    /// the span the tag has been seen over.
+    pub fn record_alias(
+        &self,
+        global_id: i32,
+        name: &str,
+        seen_at: &str,
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO aliases (global_id, name, first_seen, last_seen)
+            VALUES (?1, ?2, ?3, ?3)
+            ON CONFLICT (global_id, name) DO UPDATE SET
+                first_seen = min(first_seen, excluded.first_seen),
+                last_seen = max(last_seen, excluded.last_seen)",
+            params![global_id, name, seen_at],
+        )?;
+
+        Ok(())
+    }
+
+    /// Records what an event said about a player's profile. Details from an
+    /// older event than the saved profile only fill in what is missing.
+    pub fn record_profile(
+        &self,
+        global_id: i32,
+        profile: &PlayerProfile,
+        seen_at: &str,
+    ) -> Result<(), Error> {
+        let upsert_stmt = format!(
+            "INSERT INTO profiles (global_id, prefix, slug, country, state,
+                city, twitter, discord, seen_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            {}",
+            PROFILE_UPSERT
+        );
+        self.conn.execute(
+            &upsert_stmt,
+            params![
+                global_id,
+                profile.prefix,
+                profile.slug,
+                profile.country,
+                profile.state,
+                profile.city,
+                profile.twitter,
+                profile.discord,
+                seen_at,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Finds the name of a game that events have been imported for, given
+    /// its name in any case or its smash.gg id.
+    pub fn select_game_name(&self, game: &str) -> Result<Option<String>, Error> {
+        let game_id = game.trim().parse::<i32>().ok();
+        self.conn
+            .query_row(
+                "SELECT game_name FROM events
+                WHERE game_id = ?1 OR game_name = ?2 COLLATE NOCASE
+                UNION ALL
+                SELECT game_name FROM sets WHERE game_name = ?2 COLLATE NOCASE
+                LIMIT 1",
+                params![game_id, game.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Selects the location of every player with a profile, keyed by their
+    /// global id.
+    pub fn select_locations(&self) -> Result<HashMap<i32, PlayerProfile>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT global_id, country, state, city FROM profiles")?;
+        let profile_iter = stmt.query_map([], |row| {
+            let profile = PlayerProfile {
+                country: row.get(1)?,
+                state: row.get(2)?,
+                city: row.get(3)?,
+                ..PlayerProfile::default()
+            };
+            Ok((row.get(0)?, profile))
+        })?;
+
+        profile_iter.collect()
+    }
+
+    /// Replaces the ranks of a region's players within a table. The players
+    /// are given from first to last.
+    pub fn replace_regional_ranks(
+        &self,
+        region: &str,
+        table_name: &str,
+        global_ids: &[i32],
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            "DELETE FROM regional_ranks WHERE region = ?1 AND table_name = ?2",
+            params![region, table_name],
+        )?;
+        for (rank, global_id) in global_ids.iter().enumerate() {
+            self.conn.execute(
+                "INSERT INTO regional_ranks (region, table_name, global_id, rank)
+                VALUES (?1, ?2, ?3, ?4)",
+                params![region, table_name, global_id, rank as i32 + 1],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Renames every player in a table to the tag they were seen under most
+    /// recently.
+    pub fn refresh_names(&self, table_name: &str) -> Result<(), Error> {
+        let refresh_stmt = format!(
+            "UPDATE {0} SET name = (
+                SELECT name FROM aliases
+                WHERE aliases.global_id = {0}.global_id
+                ORDER BY last_seen DESC, first_seen DESC
+                LIMIT 1
+            )
+            WHERE global_id IN (SELECT global_id FROM aliases)",
+            clean_string(table_name)
+        );
+        self.conn.execute(&refresh_stmt, [])?;
+
+        Ok(())
+    }
+
+    /// Selects the name of every table that players or teams are rated in.
+    /// They are found by a column every version of the tables has had, so
+    /// tables made by older versions are found too.
+    pub fn select_rating_tables(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.name FROM sqlite_master AS m, pragma_table_info(m.name) AS p
+            WHERE m.type = 'table' AND p.name = 'tournament_wins'",
+        )?;
+        let table_iter = stmt.query_map([], |row| row.get(0))?;
+
+        table_iter.collect()
+    }
+
+    /// Returns the global id a player was merged into, or their own id if
+    /// they haven't been merged.
+    pub fn select_merged_id(&self, global_id: i32) -> Result<i32, Error> {
+        let merged_into = self
+            .conn
+            .query_row(
+                "SELECT merged_into FROM merged_players WHERE global_id = ?1",
+                params![global_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(merged_into.unwrap_or(global_id))
+    }
+
+    /// Selects the tag a player was seen under most recently.
+    pub fn select_latest_name(
+        &self,
+        global_id: i32,
+    ) -> Result<Option<String>, Error> {
+        self.conn
+            .query_row(
+                "SELECT name FROM aliases WHERE global_id = ?1
+                ORDER BY last_seen DESC, first_seen DESC
+                LIMIT 1",
+                params![global_id],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Returns true if a player has played a set or been seen under a tag.
+    pub fn has_player(&self, global_id: i32) -> Result<bool, Error> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM aliases WHERE global_id = ?1)
+                OR EXISTS (SELECT 1 FROM sets WHERE is_team = 0
+                    AND ?1 IN (player_one_global_id, player_two_global_id))",
+            params![global_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Moves everything recorded about one player to another: their sets,
+    /// their tags, their profile and any players merged into them. The
+    /// player is recorded as merged so sets imported later go to the other
+    /// player. Their rows in the rating tables and teams are left for the
+    /// caller.
+    pub fn merge_player(&self, global_id: i32, merged_into: i32) -> Result<(), Error> {
+        for column in ["player_one_global_id", "player_two_global_id"] {
+            let update_stmt = format!(
+                "UPDATE sets SET {0} = ?2 WHERE {0} = ?1 AND is_team = 0",
+                column
+            );
+            self.conn
+                .execute(&update_stmt, params![global_id, merged_into])?;
+        }
+
+        self.conn.execute(
+            "INSERT INTO aliases (global_id, name, first_seen, last_seen)
+            SELECT ?2, name, first_seen, last_seen FROM aliases
+            WHERE global_id = ?1
+            ON CONFLICT (global_id, name) DO UPDATE SET
+                first_seen = min(first_seen, excluded.first_seen),
+                last_seen = max(last_seen, excluded.last_seen)",
+            params![global_id, merged_into],
+        )?;
+        self.conn.execute(
+            "DELETE FROM aliases WHERE global_id = ?1",
+            params![global_id],
+        )?;
+
+        let profile_stmt = format!(
+            "INSERT INTO profiles (global_id, prefix, slug, country, state,
+                city, twitter, discord, seen_at)
+            SELECT ?2, prefix, slug, country, state, city, twitter, discord,
+                seen_at
+            FROM profiles WHERE global_id = ?1
+            {}",
+            PROFILE_UPSERT
+        );
+        self.conn
+            .execute(&profile_stmt, params![global_id, merged_into])?;
+        self.conn.execute(
+            "DELETE FROM profiles WHERE global_id = ?1",
+            params![global_id],
+        )?;
+
+        self.conn.execute(
+            "UPDATE merged_players SET merged_into = ?2 WHERE merged_into = ?1",
+            params![global_id, merged_into],
+        )?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO merged_players (global_id, merged_into)
+            VALUES (?1, ?2)",
+            params![global_id, merged_into],
+        )?;
+
+        Ok(())
+    }
+
+    /// Selects the id and name of every team a player is a member of.
+    pub fn select_member_teams(
+        &self,
+        global_id: i32,
+    ) -> Result<Vec<(i32, String)>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT teams.team_id, teams.name FROM teams
+            JOIN team_members ON team_members.team_id = teams.team_id
+            WHERE team_members.global_id = ?1",
+        )?;
+        let team_iter =
+            stmt.query_map(params![global_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        team_iter.collect()
+    }
+
+    /// Moves the sets and entrants of one team to another and removes the
+    /// first team.
+    pub fn merge_team(&self, team_id: i32, merged_into: i32) -> Result<(), Error> {
+        for column in ["player_one_global_id", "player_two_global_id"] {
+            let update_stmt = format!(
+                "UPDATE sets SET {0} = ?2 WHERE {0} = ?1 AND is_team = 1",
+                column
+            );
+            self.conn.execute(&update_stmt, params![team_id, merged_into])?;
+        }
+        self.conn.execute(
+            "UPDATE event_teams SET team_id = ?2 WHERE team_id = ?1",
+            params![team_id, merged_into],
+        )?;
+        self.conn.execute(
+            "DELETE FROM team_members WHERE team_id = ?1",
+            params![team_id],
+        )?;
+        self.conn
+            .execute("DELETE FROM teams WHERE team_id = ?1", params![team_id])?;
+
+        Ok(())
+    }
+
+    /// Removes every player from a table.
+    pub fn clear_players(&self, table_name: &str) -> Result<(), Error> {
+        let delete_stmt = format!("DELETE FROM {}", clean_string(table_name));
+        self.conn.execute(&delete_stmt, [])?;
+
+        Ok(())
+    }
+
+    /// Removes an imported event and every set recorded for it.
+    pub fn delete_event(&self, event_id: i32) -> Result<(), Error> {
+        self.conn
+            .execute("DELETE FROM sets WHERE event_id = ?1", params![event_id])?;
+        self.conn.execute(
+            "DELETE FROM event_teams WHERE event_id = ?1",
+            params![event_id],
+        )?;
+        self.conn
+            .execute("DELETE FROM events WHERE event_id = ?1", params![event_id])?;
+
+        Ok(())
+    }
+}
+
+// Responses from smash.gg cached in the database, so requests made again
+// aren't sent over the network. The cache has its own connection to the
+// database, since requests are sent while the ratings are being recorded.
+pub struct ResponseCache {
+    conn: Connection,
+}
+
+impl ResponseCache {
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+
+        // Responses are keyed by a hash of the query and variables sent. A
+        // response without an expiry time is kept forever.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS response_cache (
+                key         TEXT NOT NULL PRIMARY KEY,
+                body        TEXT NOT NULL,
+                fetched_at  INTEGER NOT NULL,
+                expires_at  INTEGER
+            )",
+            [],
+        )?;
+
+        Ok(ResponseCache { conn })
+    }
+
+    /// Selects the cached response to a request if it hasn't expired.
+    pub fn get(&self, key: &str, now: i64) -> Result<Option<String>, Error> {
+        self.conn
+            .query_row(
+                "SELECT body FROM response_cache
+                WHERE key = ?1 AND (expires_at IS NULL OR expires_at > ?2)",
+                params![key, now],
+                |row| row.get(0),
+            )
+            .optional()
+    }
+
+    /// Caches the response to a request until the given time, or forever.
+    pub fn insert(
+        &self,
+        key: &str,
+        body: &str,
+        now: i64,
+        expires_at: Option<i64>,
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO response_cache (key, body, fetched_at,
+                expires_at)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![key, body, now, expires_at],
+        )?;
+
         Ok(())
     }
 }
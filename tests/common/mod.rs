@@ -0,0 +1,95 @@
+#![allow(dead_code)]
+
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::{env, fs};
+
+/// The directory of the responses recorded for a tournament with --record.
+pub fn fixtures(slug: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(slug)
+}
+
+/// An empty directory to run the program in, named after the test.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!(
+        "smashgg_elo-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs the program in a directory on the database there, without a
+/// terminal and without anything from the environment that would change
+/// how it runs.
+pub fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_smashgg_elo"))
+        .current_dir(dir)
+        .env_remove("SMASHGG_TOKEN")
+        .env_remove("SMASHGG_DB")
+        .env_remove("SMASHGG_CONFIG")
+        .env_remove("SMASHGG_ENDPOINT")
+        .arg("--db")
+        .arg(dir.join("smashgg.db3"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// Runs the program and fails the test unless it succeeds.
+pub fn run_ok(dir: &Path, args: &[&str]) {
+    let output = run(dir, args);
+    assert!(
+        output.status.success(),
+        "{:?} failed with {}: {}",
+        args,
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Ingests an event of a tournament from its recorded responses.
+pub fn ingest(dir: &Path, slug: &str, event_id: &str, extra: &[&str]) {
+    let replay = fixtures(slug);
+    let mut args = vec![
+        "ingest",
+        "--slug",
+        slug,
+        "--event-id",
+        event_id,
+        "--no-cache",
+        "--replay",
+        replay.to_str().unwrap(),
+    ];
+    args.extend(extra);
+    run_ok(dir, &args);
+}
+
+/// Opens the database the program was run on.
+pub fn open(dir: &Path) -> Connection {
+    Connection::open(dir.join("smashgg.db3")).unwrap()
+}
+
+/// Selects every row of a query as strings, so whole tables can be
+/// compared.
+pub fn rows(conn: &Connection, query: &str) -> Vec<Vec<String>> {
+    let mut stmt = conn.prepare(query).unwrap();
+    let columns = stmt.column_count();
+    stmt.query_map([], |row| {
+        (0..columns)
+            .map(|i| {
+                let value: rusqlite::types::Value = row.get(i)?;
+                Ok(format!("{:?}", value))
+            })
+            .collect()
+    })
+    .unwrap()
+    .collect::<Result<_, _>>()
+    .unwrap()
+}
@@ -0,0 +1,252 @@
+mod common;
+
+// Tournament "t" in tests/fixtures/t has three events. In Melee singles,
+// event 1, six players play each other once in a round robin. Ultimate
+// singles, event 2, is the same except that the last player is a guest
+// without an account. Melee doubles, event 3, is a round robin of three
+// teams of two.
+#[test]
+fn replays_a_recorded_event_into_the_database() {
+    let dir = common::scratch_dir("replay");
+    common::ingest(&dir, "t", "1", &[]);
+
+    let conn = common::open(&dir);
+
+    let (sets, games, events): (i64, i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), SUM(player_one_score + player_two_score),
+                COUNT(DISTINCT event_id)
+            FROM sets",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!((sets, games, events), (15, 37, 1));
+
+    let first_set: (i64, String, i64, i64, String, i64, f64, String, String) =
+        conn.query_row(
+            "SELECT player_one_global_id, player_one_name, player_one_score,
+                player_two_global_id, player_two_name, player_two_score,
+                player_two_elo_delta, game_name, set_time
+            FROM sets ORDER BY id LIMIT 1",
+            [],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                ))
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        first_set,
+        (
+            1000,
+            "P0".to_owned(),
+            0,
+            1001,
+            "P1".to_owned(),
+            1,
+            16.0,
+            "Melee".to_owned(),
+            "2023-11-14T22:14:20+00:00".to_owned()
+        )
+    );
+
+    let mut statement = conn
+        .prepare(
+            "SELECT global_id, name, rank, wins, losses, num_tournaments
+            FROM players ORDER BY rank",
+        )
+        .unwrap();
+    let players: Vec<(i64, String, i64, i64, i64, i64)> = statement
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
+        })
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let expected = [
+        (1001, "P1", 1, 9, 2, 1),
+        (1000, "P0", 2, 8, 3, 1),
+        (1002, "P2", 3, 7, 6, 1),
+        (1003, "P3", 4, 6, 7, 1),
+        (1004, "P4", 5, 4, 9, 1),
+        (1005, "P5", 6, 3, 10, 1),
+    ]
+    .map(|(id, name, rank, wins, losses, tournaments)| {
+        (id, name.to_owned(), rank, wins, losses, tournaments)
+    });
+    assert_eq!(players, expected);
+
+    let elo: f64 = conn
+        .query_row(
+            "SELECT elo FROM players WHERE global_id = 1001",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!((elo - 1584.401).abs() < 0.001, "elo was {}", elo);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Every column of the sets and players that importing an event changes.
+const SETS_QUERY: &str = "SELECT player_one_global_id, player_one_elo,
+        player_one_score, player_one_elo_delta, player_two_global_id,
+        player_two_elo, player_two_score, player_two_elo_delta, set_time,
+        event_id
+    FROM sets ORDER BY set_time, id";
+const PLAYERS_QUERY: &str = "SELECT * FROM players ORDER BY global_id";
+
+#[test]
+fn skips_an_event_that_was_already_imported() {
+    let dir = common::scratch_dir("skip");
+    common::ingest(&dir, "t", "1", &[]);
+    let conn = common::open(&dir);
+    let sets = common::rows(&conn, SETS_QUERY);
+    let players = common::rows(&conn, PLAYERS_QUERY);
+
+    common::ingest(&dir, "t", "1", &[]);
+
+    assert_eq!(sets.len(), 15);
+    assert_eq!(common::rows(&conn, SETS_QUERY), sets);
+    assert_eq!(common::rows(&conn, PLAYERS_QUERY), players);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reimports_an_event_in_place_of_the_first_import() {
+    let dir = common::scratch_dir("reimport");
+    common::ingest(&dir, "t", "1", &[]);
+    let conn = common::open(&dir);
+    let sets = common::rows(&conn, SETS_QUERY);
+    let players = common::rows(&conn, PLAYERS_QUERY);
+
+    common::ingest(&dir, "t", "1", &["--reimport"]);
+
+    assert_eq!(common::rows(&conn, SETS_QUERY), sets);
+    assert_eq!(common::rows(&conn, PLAYERS_QUERY), players);
+    let events: i64 = conn
+        .query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(events, 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn records_guests_as_unverified_players() {
+    let dir = common::scratch_dir("guests");
+    common::ingest(&dir, "t", "2", &[]);
+
+    let conn = common::open(&dir);
+    for table in ["players", "ultimate"] {
+        let query = format!(
+            "SELECT global_id, name, verified FROM {} ORDER BY global_id",
+            table
+        );
+        let players: Vec<Vec<String>> = [
+            ["Integer(-19140727)", "Text(\"P5\")", "Integer(0)"],
+            ["Integer(1000)", "Text(\"P0\")", "Integer(1)"],
+            ["Integer(1001)", "Text(\"P1\")", "Integer(1)"],
+            ["Integer(1002)", "Text(\"P2\")", "Integer(1)"],
+            ["Integer(1003)", "Text(\"P3\")", "Integer(1)"],
+            ["Integer(1004)", "Text(\"P4\")", "Integer(1)"],
+        ]
+        .map(|row| row.map(str::to_owned).to_vec())
+        .to_vec();
+        assert_eq!(common::rows(&conn, &query), players, "{}", table);
+    }
+    let guest_sets: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM sets
+            WHERE -19140727 IN (player_one_global_id, player_two_global_id)",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(guest_sets, 5);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rates_doubles_by_team() {
+    let dir = common::scratch_dir("doubles");
+    common::ingest(&dir, "t", "3", &[]);
+
+    let conn = common::open(&dir);
+    assert_eq!(
+        common::rows(
+            &conn,
+            "SELECT member_ids, name FROM teams ORDER BY member_ids"
+        ),
+        [
+            ["Text(\"1000,1001\")", "Text(\"P0 / P1\")"],
+            ["Text(\"1002,1003\")", "Text(\"P2 / P3\")"],
+            ["Text(\"1004,1005\")", "Text(\"P4 / P5\")"],
+        ]
+        .map(|row| row.map(str::to_owned).to_vec())
+    );
+    let members: Vec<Vec<String>> = (0..6)
+        .map(|player| {
+            vec![
+                format!(
+                    "Text(\"{},{}\")",
+                    1000 + player / 2 * 2,
+                    1001 + player / 2 * 2
+                ),
+                format!("Integer({})", 1000 + player),
+                format!("Text(\"P{}\")", player),
+            ]
+        })
+        .collect();
+    assert_eq!(
+        common::rows(
+            &conn,
+            "SELECT teams.member_ids, team_members.global_id, team_members.name
+            FROM team_members JOIN teams USING (team_id)
+            ORDER BY team_members.global_id",
+        ),
+        members
+    );
+
+    // Each team is rated in the doubles tables under its team id, and the
+    // sets are between teams. Nobody is rated in the singles tables.
+    let teams =
+        common::rows(&conn, "SELECT team_id FROM teams ORDER BY team_id");
+    for table in ["doubles", "meleedoubles"] {
+        let query =
+            format!("SELECT global_id FROM {} ORDER BY global_id", table);
+        assert_eq!(common::rows(&conn, &query), teams, "{}", table);
+    }
+    assert!(common::rows(&conn, "SELECT * FROM players").is_empty());
+    let (sets, team_sets): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), SUM(is_team) FROM sets
+            WHERE player_one_global_id IN (SELECT team_id FROM teams)
+                AND player_two_global_id IN (SELECT team_id FROM teams)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((sets, team_sets), (3, 3));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::fs;
+
+// Counts the rows a query selects.
+fn count(conn: &rusqlite::Connection, query: &str) -> usize {
+    common::rows(conn, query).len()
+}
+
+#[test]
+fn merges_a_guest_into_their_account() {
+    let dir = common::scratch_dir("merge-guest");
+    common::ingest(&dir, "t", "1", &[]);
+    common::ingest(&dir, "t", "2", &[]);
+
+    common::run_ok(&dir, &["merge-players", "-19140727", "1005"]);
+
+    let conn = common::open(&dir);
+    let guest_sets = "SELECT id FROM sets
+        WHERE -19140727 IN (player_one_global_id, player_two_global_id)";
+    assert_eq!(count(&conn, guest_sets), 0);
+    assert_eq!(
+        count(&conn, "SELECT id FROM sets WHERE 1005 IN (player_one_global_id, player_two_global_id)"),
+        10
+    );
+    assert_eq!(
+        common::rows(&conn, "SELECT * FROM merged_players"),
+        [["Integer(-19140727)", "Integer(1005)"]
+            .map(str::to_owned)
+            .to_vec()]
+    );
+    assert_eq!(
+        count(&conn, "SELECT * FROM aliases WHERE global_id = -19140727"),
+        0
+    );
+
+    // The guest's results now count for the account in every table.
+    for table in ["players", "melee", "ultimate"] {
+        let query =
+            format!("SELECT global_id FROM {} WHERE global_id < 0", table);
+        assert_eq!(count(&conn, &query), 0, "{}", table);
+    }
+    assert_eq!(
+        common::rows(
+            &conn,
+            "SELECT num_games, wins, losses, num_tournaments, verified
+            FROM players WHERE global_id = 1005",
+        ),
+        [[
+            "Integer(26)",
+            "Integer(6)",
+            "Integer(20)",
+            "Integer(2)",
+            "Integer(1)"
+        ]
+        .map(str::to_owned)
+        .to_vec()]
+    );
+
+    // Importing the guest's event again records them as the account.
+    common::ingest(&dir, "t", "2", &["--reimport"]);
+    assert_eq!(count(&conn, guest_sets), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replaces_a_merged_player_in_their_teams() {
+    let dir = common::scratch_dir("merge-team");
+    common::ingest(&dir, "t", "3", &[]);
+
+    common::run_ok(&dir, &["merge-players", "1000", "1002"]);
+
+    // The team of 1000 and 1001 is now 1001 and 1002, who is listed under
+    // their own tag.
+    let conn = common::open(&dir);
+    assert_eq!(
+        common::rows(
+            &conn,
+            "SELECT teams.member_ids, team_members.global_id, team_members.name
+            FROM team_members JOIN teams USING (team_id)
+            WHERE teams.member_ids = '1001,1002'
+            ORDER BY team_members.global_id",
+        ),
+        [
+            ["Text(\"1001,1002\")", "Integer(1001)", "Text(\"P1\")"],
+            ["Text(\"1001,1002\")", "Integer(1002)", "Text(\"P2\")"],
+        ]
+        .map(|row| row.map(str::to_owned).to_vec())
+    );
+    assert_eq!(
+        count(&conn, "SELECT * FROM team_members WHERE global_id = 1000"),
+        0
+    );
+    assert_eq!(count(&conn, "SELECT * FROM teams"), 3);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_merges_that_cant_be_undone() {
+    let dir = common::scratch_dir("merge-rejected");
+    common::ingest(&dir, "t", "3", &[]);
+    let conn = common::open(&dir);
+    let sets = common::rows(&conn, "SELECT * FROM sets ORDER BY id");
+
+    // A mistyped id, a player merged into themselves and two teammates.
+    for (from, into) in [("1000", "999999"), ("1000", "1000"), ("1000", "1001")]
+    {
+        let output = common::run(&dir, &["merge-players", from, into]);
+        assert_eq!(output.status.code(), Some(2), "{} into {}", from, into);
+    }
+
+    assert_eq!(common::rows(&conn, "SELECT * FROM sets ORDER BY id"), sets);
+    assert_eq!(count(&conn, "SELECT * FROM merged_players"), 0);
+    assert_eq!(count(&conn, "SELECT * FROM team_members"), 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+
+// The columns of the rating tables as they were first released.
+const ORIGINAL_COLUMNS: &str = "global_id, name, rank, elo, num_games, wins,
+    losses, win_loss_ratio, num_tournaments, tournament_wins";
+
+#[test]
+fn recomputes_the_ratings_it_started_with() {
+    // Sets record the changes of the first rating system, which for
+    // Glicko-2 are only known once the tournament is over.
+    for rating_system in ["elo,glicko2", "glicko2,elo"] {
+        let dir = common::scratch_dir(&format!("recompute-{}", rating_system));
+        common::ingest(&dir, "t", "1", &["--rating-system", rating_system]);
+
+        let conn = common::open(&dir);
+        let queries = [
+            "SELECT * FROM players ORDER BY global_id",
+            "SELECT * FROM melee ORDER BY global_id",
+            "SELECT id, player_one_elo, player_one_elo_delta, player_two_elo,
+                player_two_elo_delta
+            FROM sets ORDER BY id",
+        ];
+        let tables: Vec<_> = queries
+            .iter()
+            .map(|query| common::rows(&conn, query))
+            .collect();
+
+        common::run_ok(&dir, &["recompute"]);
+
+        for (query, rows) in queries.iter().zip(&tables) {
+            assert!(!rows.is_empty(), "{}", query);
+            assert_eq!(&common::rows(&conn, query), rows, "{}", rating_system);
+        }
+        let unchanged: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sets WHERE player_one_elo_delta = 0",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(unchanged, 0, "{}", rating_system);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[test]
+fn recomputes_tables_made_by_older_versions() {
+    let dir = common::scratch_dir("recompute-old-tables");
+    common::ingest(&dir, "t", "1", &[]);
+
+    let conn = common::open(&dir);
+    let players_query = format!("SELECT {} FROM players", ORIGINAL_COLUMNS);
+    let melee_query = format!("SELECT {} FROM melee", ORIGINAL_COLUMNS);
+    let players = common::rows(&conn, &players_query);
+    let melee = common::rows(&conn, &melee_query);
+
+    // Rebuild the game's table the way the first version made it, before
+    // the Glicko-2 and verified columns were added.
+    conn.execute_batch(&format!(
+        "ALTER TABLE melee RENAME TO melee_new;
+        CREATE TABLE melee (
+            global_id        INTEGER NOT NULL PRIMARY KEY UNIQUE,
+            name             TEXT NOT NULL,
+            rank             INTEGER DEFAULT 0 NOT NULL,
+            elo              REAL DEFAULT 1500.0 NOT NULL,
+            num_games        INTEGER DEFAULT 0 NOT NULL,
+            wins             INTEGER DEFAULT 0 NOT NULL,
+            losses           INTEGER DEFAULT 0 NOT NULL,
+            win_loss_ratio   REAL DEFAULT 0 NOT NULL,
+            num_tournaments  INTEGER DEFAULT 0 NOT NULL,
+            tournament_wins  INTEGER DEFAULT 0 NOT NULL
+        );
+        INSERT INTO melee SELECT {0} FROM melee_new;
+        DROP TABLE melee_new;",
+        ORIGINAL_COLUMNS
+    ))
+    .unwrap();
+    drop(conn);
+
+    common::run_ok(&dir, &["recompute"]);
+
+    let conn = common::open(&dir);
+    assert_eq!(common::rows(&conn, &players_query), players);
+    assert_eq!(common::rows(&conn, &melee_query), melee);
+
+    fs::remove_dir_all(&dir).unwrap();
+}